@@ -0,0 +1,24 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use lw_lwnx::crc::{crc16_bitwise, Crc16};
+use lw_lwnx::lwnx::MAX_PACKET_SIZE;
+
+fn crc(c: &mut Criterion) {
+    let mut group = c.benchmark_group("crc16");
+
+    for size in [22, MAX_PACKET_SIZE, 64 * 1024] {
+        let data: Vec<u8> = (0..size).map(|i| (i * 31 + 7) as u8).collect();
+        group.throughput(Throughput::Bytes(size as u64));
+
+        group.bench_with_input(BenchmarkId::new("bitwise", size), &data, |b, data| {
+            b.iter(|| crc16_bitwise(black_box(data)))
+        });
+        group.bench_with_input(BenchmarkId::new("table", size), &data, |b, data| {
+            b.iter(|| Crc16::checksum(black_box(data)))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, crc);
+criterion_main!(benches);
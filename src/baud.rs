@@ -0,0 +1,150 @@
+//! Serial baud rates supported by LightWare devices.
+
+use crate::commands::{BaudRate, ProductName};
+use crate::lwnx::{engage_lwnx_mode, DeviceContext, LwnxError, UserPlatform};
+
+/// Supported baud rates, indexed by the value of the device baud rate setting.
+pub const BAUD_RATES: [u32; 8] = [9600, 19200, 38400, 57600, 115200, 230400, 460800, 921600];
+
+/// Finds the baud rate the device answers at.
+///
+/// Switches the platform to each rate in `baud_rates` in turn, engages LWNX
+/// mode and reads the product name. Returns the first rate that answered and
+/// leaves the platform at that rate. Rates are tried in order, so the most
+/// likely rate should come first. Rates the platform cannot switch to are
+/// skipped; if it cannot switch to any of them, the last error it gave is
+/// returned.
+pub fn detect_baud_rate<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    baud_rates: &[u32],
+) -> Result<u32, LwnxError> {
+    let mut switch_error = None;
+    let mut switched = false;
+
+    for &baud_rate in baud_rates {
+        if let Err(e) = device_context
+            .user_platform
+            .set_baud_rate_callback(baud_rate)
+        {
+            switch_error = Some(e);
+            continue;
+        }
+        switched = true;
+        engage_lwnx_mode(device_context)?;
+
+        match device_context.read::<ProductName>() {
+            Ok(_) => return Ok(baud_rate),
+            Err(e) if is_no_answer(&e) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    match switch_error {
+        Some(e) if !switched => Err(e),
+        _ => Err(LwnxError::BaudRateNotDetected),
+    }
+}
+
+/// Returns true for the errors a device gives when the link runs at the wrong
+/// rate: silence, or bytes that do not form a valid response. Other platform
+/// errors, like a port that has gone away, are not.
+fn is_no_answer(error: &LwnxError) -> bool {
+    error.is_timeout()
+        || matches!(
+            error,
+            LwnxError::CommandRetriesExhausted { .. }
+                | LwnxError::CrcMismatch { .. }
+                | LwnxError::InvalidData
+                | LwnxError::UnexpectedResponse { .. }
+                | LwnxError::UnexpectedResponseLength { .. }
+        )
+}
+
+/// Returns the device setting value for `baud_rate`.
+pub fn baud_rate_index(baud_rate: u32) -> Result<u8, LwnxError> {
+    BAUD_RATES
+        .iter()
+        .position(|&rate| rate == baud_rate)
+        .map(|index| index as u8)
+        .ok_or(LwnxError::UnsupportedBaudRate(baud_rate))
+}
+
+/// Reads the baud rate the device is set to.
+pub fn read_baud_rate<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+) -> Result<u32, LwnxError> {
+    let index = device_context.read::<BaudRate>()?;
+    BAUD_RATES
+        .get(index as usize)
+        .copied()
+        .ok_or(LwnxError::InvalidData)
+}
+
+/// Changes the baud rate of the device and switches the platform to match.
+///
+/// The change is confirmed by reading the setting back at the new rate, even if
+/// the echo of the write was lost, since the device may have switched anyway.
+/// If that fails the platform returns to the previous rate, and if the device
+/// still answers there its setting is restored. The error returned is the first
+/// one that made the change fail. The setting is not saved, so a power cycle
+/// also brings the device back to the previous rate.
+pub fn set_baud_rate<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    baud_rate: u32,
+) -> Result<(), LwnxError> {
+    let index = baud_rate_index(baud_rate)?;
+    let previous_baud_rate = read_baud_rate(device_context)?;
+    if previous_baud_rate == baud_rate {
+        return Ok(());
+    }
+
+    // The device answers at the previous rate before switching.
+    let written = device_context.write::<BaudRate>(&index);
+    let confirmed = device_context
+        .user_platform
+        .set_baud_rate_callback(baud_rate)
+        .and_then(|_| confirm_baud_rate(device_context, baud_rate));
+
+    match confirmed {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            restore_baud_rate(device_context, previous_baud_rate);
+            Err(written.err().unwrap_or(e))
+        }
+    }
+}
+
+/// Returns the platform to `baud_rate` and, if the device answers there with
+/// another setting, sets the device back too. Errors are ignored, as this only
+/// runs once a change has already failed.
+fn restore_baud_rate<T: UserPlatform>(device_context: &mut DeviceContext<T>, baud_rate: u32) {
+    if device_context
+        .user_platform
+        .set_baud_rate_callback(baud_rate)
+        .is_err()
+    {
+        return;
+    }
+
+    if engage_lwnx_mode(device_context).is_ok()
+        && read_baud_rate(device_context).is_ok_and(|rate| rate != baud_rate)
+    {
+        if let Ok(index) = baud_rate_index(baud_rate) {
+            let _ = device_context.write::<BaudRate>(&index);
+        }
+    }
+}
+
+/// Checks that the device answers and reports `baud_rate` as its setting.
+fn confirm_baud_rate<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    baud_rate: u32,
+) -> Result<(), LwnxError> {
+    engage_lwnx_mode(device_context)?;
+
+    if read_baud_rate(device_context)? != baud_rate {
+        return Err(LwnxError::InvalidData);
+    }
+
+    Ok(())
+}
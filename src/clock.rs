@@ -0,0 +1,122 @@
+//! Time sources used to measure command timeouts.
+
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+use crate::lwnx::{LwnxError, UserPlatform};
+
+/// A monotonic millisecond time source.
+pub trait Clock {
+    fn now_ms(&self) -> u64;
+
+    /// Waits for `duration_ms` as measured by this clock.
+    fn delay_ms(&self, duration_ms: u64);
+}
+
+/// Clock backed by `std::time::Instant` and `std::thread::sleep`.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdClock;
+
+#[cfg(feature = "std")]
+impl Clock for StdClock {
+    fn now_ms(&self) -> u64 {
+        static START: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+        START
+            .get_or_init(std::time::Instant::now)
+            .elapsed()
+            .as_millis() as u64
+    }
+
+    fn delay_ms(&self, duration_ms: u64) {
+        std::thread::sleep(std::time::Duration::from_millis(duration_ms));
+    }
+}
+
+/// Clock that only moves when told to, for testing timeouts without waiting.
+///
+/// Clones share the same time. Delays advance the clock instantly. With
+/// `PollStrategy::Busy` nothing waits, so set an auto advance step to let
+/// timeouts expire.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default)]
+pub struct ManualClock {
+    time: Arc<AtomicU64>,
+    auto_advance: Arc<AtomicU64>,
+}
+
+#[cfg(feature = "std")]
+impl ManualClock {
+    pub fn new() -> ManualClock {
+        ManualClock::default()
+    }
+
+    pub fn set(&self, time_ms: u64) {
+        self.time.store(time_ms, Ordering::SeqCst);
+    }
+
+    pub fn advance(&self, duration_ms: u64) {
+        self.time.fetch_add(duration_ms, Ordering::SeqCst);
+    }
+
+    /// Advances the clock by `step_ms` every time it is read.
+    pub fn set_auto_advance(&self, step_ms: u64) {
+        self.auto_advance.store(step_ms, Ordering::SeqCst);
+    }
+}
+
+#[cfg(feature = "std")]
+impl Clock for ManualClock {
+    fn now_ms(&self) -> u64 {
+        let step = self.auto_advance.load(Ordering::SeqCst);
+        self.time.fetch_add(step, Ordering::SeqCst)
+    }
+
+    fn delay_ms(&self, duration_ms: u64) {
+        self.advance(duration_ms);
+    }
+}
+
+/// Wraps a `UserPlatform` so its time and delays come from `clock`.
+pub struct WithClock<P: UserPlatform, C: Clock> {
+    pub inner: P,
+    pub clock: C,
+}
+
+impl<P: UserPlatform, C: Clock> WithClock<P, C> {
+    pub fn new(inner: P, clock: C) -> WithClock<P, C> {
+        WithClock { inner, clock }
+    }
+}
+
+impl<P: UserPlatform, C: Clock> UserPlatform for WithClock<P, C> {
+    fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        self.inner.write_callback(data)
+    }
+
+    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError> {
+        self.inner.read_callback(data)
+    }
+
+    fn delay_callback(&mut self, duration_ms: u64) {
+        self.clock.delay_ms(duration_ms);
+    }
+
+    fn set_read_timeout_callback(&mut self, timeout_ms: u64) -> Result<(), LwnxError> {
+        self.inner.set_read_timeout_callback(timeout_ms)
+    }
+
+    fn read_timeout_callback(&mut self) -> Option<u64> {
+        self.inner.read_timeout_callback()
+    }
+
+    fn set_baud_rate_callback(&mut self, baud_rate: u32) -> Result<(), LwnxError> {
+        self.inner.set_baud_rate_callback(baud_rate)
+    }
+
+    fn time_ms(&mut self) -> u64 {
+        self.clock.now_ms()
+    }
+}
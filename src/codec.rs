@@ -0,0 +1,134 @@
+//! LWNX packet framing independent of any transport.
+//!
+//! [`LwnxCodec`] encodes and decodes packets over plain byte slices so the
+//! protocol can be carried over sockets, files or pipes without a
+//! [`crate::lwnx::DeviceContext`]. With the `codec` feature it also implements
+//! the `tokio_util` codec traits for use with `Framed`.
+
+#[cfg(feature = "codec")]
+use crate::lwnx::{check_data_size, packet_size};
+use crate::lwnx::{create_packet_bytes, LinkStats, LwnxError, ParseResult, Response};
+
+/// Incremental LWNX packet encoder and decoder.
+pub struct LwnxCodec {
+    response: Response,
+    pub link_stats: LinkStats,
+}
+
+impl LwnxCodec {
+    pub fn new() -> LwnxCodec {
+        LwnxCodec {
+            response: Response::new(),
+            link_stats: LinkStats::default(),
+        }
+    }
+
+    /// Encodes a packet into `buffer` and returns the encoded bytes.
+    pub fn encode<'a>(
+        &self,
+        buffer: &'a mut [u8],
+        command_id: u8,
+        write: bool,
+        data: &[u8],
+    ) -> Result<&'a [u8], LwnxError> {
+        create_packet_bytes(buffer, command_id, write, data)
+    }
+
+    /// Feeds bytes from `src` to the decoder until a packet is complete.
+    ///
+    /// Returns the number of bytes consumed and the packet, if one was
+    /// completed. Bytes of an unfinished packet are kept by the codec, so the
+    /// next call continues where this one stopped. Packets with a bad CRC are
+    /// discarded and counted in `link_stats`.
+    pub fn decode(&mut self, src: &[u8]) -> (usize, Option<&Response>) {
+        for (index, &byte) in src.iter().enumerate() {
+            if self.link_stats.parse(&mut self.response, byte) == ParseResult::Complete {
+                return (index + 1, Some(&self.response));
+            }
+        }
+
+        (src.len(), None)
+    }
+}
+
+impl Default for LwnxCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// An owned LWNX packet.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pub command_id: u8,
+    pub write: bool,
+    pub payload: Vec<u8>,
+}
+
+#[cfg(feature = "std")]
+impl Frame {
+    /// Creates a read request, which carries no data.
+    pub fn read(command_id: u8) -> Frame {
+        Frame {
+            command_id,
+            write: false,
+            payload: Vec::new(),
+        }
+    }
+
+    /// Creates a write request carrying `payload`.
+    pub fn write(command_id: u8, payload: &[u8]) -> Frame {
+        Frame {
+            command_id,
+            write: true,
+            payload: payload.to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<&Response> for Frame {
+    fn from(response: &Response) -> Frame {
+        Frame {
+            command_id: response.get_command(),
+            write: response.is_write(),
+            payload: response.payload().to_vec(),
+        }
+    }
+}
+
+#[cfg(feature = "codec")]
+impl tokio_util::codec::Decoder for LwnxCodec {
+    type Item = Frame;
+    type Error = LwnxError;
+
+    fn decode(&mut self, src: &mut bytes::BytesMut) -> Result<Option<Frame>, LwnxError> {
+        let (consumed, frame) = match LwnxCodec::decode(self, src) {
+            (consumed, Some(response)) => (consumed, Some(Frame::from(response))),
+            (consumed, None) => (consumed, None),
+        };
+
+        bytes::Buf::advance(src, consumed);
+        Ok(frame)
+    }
+}
+
+#[cfg(feature = "codec")]
+impl tokio_util::codec::Encoder<Frame> for LwnxCodec {
+    type Error = LwnxError;
+
+    fn encode(&mut self, frame: Frame, dst: &mut bytes::BytesMut) -> Result<(), LwnxError> {
+        check_data_size(frame.payload.len())?;
+
+        let start = dst.len();
+        dst.resize(start + packet_size(frame.payload.len()), 0);
+        create_packet_bytes(
+            &mut dst[start..],
+            frame.command_id,
+            frame.write,
+            &frame.payload,
+        )?;
+        Ok(())
+    }
+}
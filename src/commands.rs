@@ -0,0 +1,241 @@
+use crate::distance::DistanceOutputConfig;
+use crate::lwnx::{self, DeviceContext, LwnxError, LwnxString, UserPlatform};
+
+/// Time to wait for the device to answer after a save or reset, in ms.
+const RESTART_TIMEOUT: u64 = 5000;
+
+/// Delay between polls while waiting for the device to answer, in ms.
+const RESTART_POLL_INTERVAL: u64 = 100;
+
+/// How a command may be accessed on the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// A value type that can be carried in a command payload.
+pub trait CommandData: Sized {
+    fn read<T: UserPlatform>(
+        device_context: &mut DeviceContext<T>,
+        command_id: u8,
+    ) -> Result<Self, LwnxError>;
+
+    /// Writes the value to a command whose payload is `size` bytes. Reads do not
+    /// need the size since the response header carries it.
+    fn write<T: UserPlatform>(
+        &self,
+        device_context: &mut DeviceContext<T>,
+        command_id: u8,
+        size: usize,
+    ) -> Result<(), LwnxError>;
+}
+
+macro_rules! impl_command_data {
+    ($data:ty, $read:ident, $write:ident) => {
+        impl CommandData for $data {
+            fn read<T: UserPlatform>(
+                device_context: &mut DeviceContext<T>,
+                command_id: u8,
+            ) -> Result<Self, LwnxError> {
+                lwnx::$read(device_context, command_id)
+            }
+
+            fn write<T: UserPlatform>(
+                &self,
+                device_context: &mut DeviceContext<T>,
+                command_id: u8,
+                _size: usize,
+            ) -> Result<(), LwnxError> {
+                lwnx::$write(device_context, command_id, *self)
+            }
+        }
+    };
+}
+
+impl_command_data!(i8, cmd_read_i8, cmd_write_i8);
+impl_command_data!(i16, cmd_read_i16, cmd_write_i16);
+impl_command_data!(i32, cmd_read_i32, cmd_write_i32);
+impl_command_data!(u8, cmd_read_u8, cmd_write_u8);
+impl_command_data!(u16, cmd_read_u16, cmd_write_u16);
+impl_command_data!(u32, cmd_read_u32, cmd_write_u32);
+
+impl CommandData for LwnxString {
+    fn read<T: UserPlatform>(
+        device_context: &mut DeviceContext<T>,
+        command_id: u8,
+    ) -> Result<Self, LwnxError> {
+        lwnx::cmd_read_string(device_context, command_id)
+    }
+
+    fn write<T: UserPlatform>(
+        &self,
+        device_context: &mut DeviceContext<T>,
+        command_id: u8,
+        size: usize,
+    ) -> Result<(), LwnxError> {
+        lwnx::cmd_write_string_sized(device_context, command_id, self, size)
+    }
+}
+
+/// Describes a single LWNX command.
+pub trait Command {
+    const ID: u8;
+    const ACCESS: Access;
+    /// Size of the command payload in bytes.
+    const SIZE: usize;
+    type Data: CommandData;
+}
+
+/// A command that can be read from the device.
+pub trait Readable: Command {}
+
+/// A command that can be written to the device.
+pub trait Writable: Command {}
+
+/// A command that restarts the device before it can answer, so it is sent
+/// without waiting for an echo. Its payload is the device token.
+pub trait Restarting: Command<Data = u16> {}
+
+macro_rules! command {
+    (@access $name:ident, Read) => {
+        impl Readable for $name {}
+    };
+    (@access $name:ident, Write) => {
+        impl Writable for $name {}
+    };
+    (@access $name:ident, ReadWrite) => {
+        impl Readable for $name {}
+        impl Writable for $name {}
+    };
+    (@access $name:ident, Restart) => {
+        impl Restarting for $name {}
+    };
+    (@kind Restart) => {
+        Access::Write
+    };
+    (@kind $access:ident) => {
+        Access::$access
+    };
+    ($(#[$meta:meta])* $name:ident, $id:expr, $access:ident, $data:ty, $size:expr) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy)]
+        pub struct $name;
+
+        impl Command for $name {
+            const ID: u8 = $id;
+            const ACCESS: Access = command!(@kind $access);
+            const SIZE: usize = $size;
+            type Data = $data;
+        }
+
+        command!(@access $name, $access);
+    };
+}
+
+command!(
+    /// Product model name.
+    ProductName, 0, Read, LwnxString, 16
+);
+command!(
+    /// Hardware revision of the device.
+    HardwareVersion, 1, Read, u32, 4
+);
+command!(
+    /// Packed firmware version of the device.
+    FirmwareVersion, 2, Read, u32, 4
+);
+command!(
+    /// Device serial number.
+    SerialNumber, 3, Read, LwnxString, 16
+);
+command!(
+    /// Free-form user data stored on the device.
+    UserData, 9, ReadWrite, LwnxString, 32
+);
+command!(
+    /// Token that must accompany save, reset and firmware commit commands.
+    Token, 10, Read, u16, 2
+);
+command!(
+    /// Saves the current settings to persistent storage.
+    SaveParameters, 12, Write, u16, 2
+);
+command!(
+    /// Restarts the device.
+    Reset, 14, Restart, u16, 2
+);
+command!(
+    /// Commits the staged firmware image and restarts the device.
+    CommitFirmware, 17, Restart, u16, 2
+);
+command!(
+    /// Bit field selecting which values are included in distance output.
+    DistanceOutput, 27, ReadWrite, DistanceOutputConfig, 4
+);
+command!(
+    /// Selects which packets the device pushes without being asked.
+    Stream, 30, ReadWrite, u32, 4
+);
+command!(
+    /// Serial baud rate, as an index into `baud::BAUD_RATES`.
+    BaudRate, 79, ReadWrite, u8, 1
+);
+
+/// Distance packet pushed by the device while the distance stream is enabled.
+///
+/// The payload layout follows the distance output configuration, so it is not
+/// readable as a plain value.
+#[derive(Debug, Clone, Copy)]
+pub struct DistanceData;
+
+impl DistanceData {
+    pub const ID: u8 = 44;
+}
+
+/// Stages a page of a firmware image. The payload is a 16 bit page index
+/// followed by the page data, so it is sent through `handle_managed_cmd`.
+#[derive(Debug, Clone, Copy)]
+pub struct StageFirmware;
+
+impl StageFirmware {
+    pub const ID: u8 = 16;
+}
+
+impl<T: UserPlatform> DeviceContext<T> {
+    /// Reads the value of a command from the device.
+    pub fn read<C: Readable>(&mut self) -> Result<C::Data, LwnxError> {
+        C::Data::read(self, C::ID)
+    }
+
+    /// Writes a new value for a command to the device.
+    pub fn write<C: Writable>(&mut self, value: &C::Data) -> Result<(), LwnxError> {
+        value.write(self, C::ID, C::SIZE)
+    }
+
+    /// Reads the token and sends a command that restarts the device, without
+    /// waiting for a reply.
+    pub fn send_restart<C: Restarting>(&mut self) -> Result<(), LwnxError> {
+        let token = self.read::<Token>()?;
+        lwnx::send_cmd(self, C::ID, true, &token.to_le_bytes())
+    }
+
+    /// Saves the current settings so they survive a power cycle.
+    ///
+    /// Waits until the device answers again before returning.
+    pub fn save_parameters(&mut self) -> Result<(), LwnxError> {
+        let token = self.read::<Token>()?;
+        self.write::<SaveParameters>(&token)?;
+        lwnx::wait_for_device(self, RESTART_TIMEOUT, RESTART_POLL_INTERVAL)
+    }
+
+    /// Restarts the device and waits until it answers again.
+    ///
+    /// The reset command is sent without waiting for a response since the
+    /// device may restart before replying.
+    pub fn reset_device(&mut self) -> Result<(), LwnxError> {
+        self.send_restart::<Reset>()?;
+        lwnx::wait_for_device(self, RESTART_TIMEOUT, RESTART_POLL_INTERVAL)
+    }
+}
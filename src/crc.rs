@@ -0,0 +1,108 @@
+//! CRC-16 used by LWNX packets: polynomial 0x1021, initial value 0, no
+//! reflection.
+
+/// Slicing-by-8 lookup tables. `TABLES[0][i]` is the CRC of byte `i`, and
+/// `TABLES[k][i]` the CRC of byte `i` followed by `k` zero bytes.
+const TABLES: [[u16; 256]; 8] = make_tables();
+
+const fn make_tables() -> [[u16; 256]; 8] {
+    let mut tables = [[0u16; 256]; 8];
+    let mut index = 0;
+
+    while index < 256 {
+        let mut crc = (index as u16) << 8;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+            bit += 1;
+        }
+        tables[0][index] = crc;
+        index += 1;
+    }
+
+    let mut k = 1;
+    while k < 8 {
+        index = 0;
+        while index < 256 {
+            let prev = tables[k - 1][index];
+            tables[k][index] = (prev << 8) ^ tables[0][(prev >> 8) as usize];
+            index += 1;
+        }
+        k += 1;
+    }
+
+    tables
+}
+
+/// Incremental table-driven CRC-16 using slicing-by-8.
+///
+/// Feeding data in several `update` calls gives the same result as a single
+/// call over the concatenated data.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Crc16(u16);
+
+impl Crc16 {
+    pub const fn new() -> Crc16 {
+        Crc16(0)
+    }
+
+    /// Returns the CRC of `data`.
+    pub fn checksum(data: &[u8]) -> u16 {
+        let mut crc = Crc16::new();
+        crc.update(data);
+        crc.value()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        let mut crc = self.0;
+
+        // Eight bytes per step through independent table lookups, so the
+        // lookups do not wait on each other.
+        let mut chunks = data.chunks_exact(8);
+        for c in &mut chunks {
+            crc = TABLES[7][(c[0] ^ (crc >> 8) as u8) as usize]
+                ^ TABLES[6][(c[1] ^ crc as u8) as usize]
+                ^ TABLES[5][c[2] as usize]
+                ^ TABLES[4][c[3] as usize]
+                ^ TABLES[3][c[4] as usize]
+                ^ TABLES[2][c[5] as usize]
+                ^ TABLES[1][c[6] as usize]
+                ^ TABLES[0][c[7] as usize];
+        }
+
+        for &b in chunks.remainder() {
+            crc = (crc << 8) ^ TABLES[0][((crc >> 8) as u8 ^ b) as usize];
+        }
+
+        self.0 = crc;
+    }
+
+    /// Returns the CRC of the data fed so far.
+    pub fn value(&self) -> u16 {
+        self.0
+    }
+}
+
+/// Computes the CRC one bit at a time. Kept as a reference for tests and
+/// benchmarks; use [`Crc16`] instead.
+pub fn crc16_bitwise(data: &[u8]) -> u16 {
+    let mut crc: u16 = 0;
+
+    for b in data {
+        let mut code = crc >> 8;
+        code ^= *b as u16;
+        code ^= code >> 4;
+        crc <<= 8;
+        crc ^= code;
+        code <<= 5;
+        crc ^= code;
+        code <<= 7;
+        crc ^= code;
+    }
+
+    crc
+}
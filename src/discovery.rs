@@ -0,0 +1,113 @@
+//! Finding LightWare devices on the available serial ports.
+
+use crate::commands::{FirmwareVersion, ProductName, SerialNumber};
+use crate::lwnx::{engage_lwnx_mode, DeviceContext, LwnxError, LwnxString, UserPlatform};
+
+#[cfg(unix)]
+use crate::linux_serialport::LinuxSerialPort;
+
+/// Identification read from a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceIdentity {
+    pub product_name: LwnxString,
+    pub firmware_version: u32,
+    pub serial_number: LwnxString,
+}
+
+/// Engages LWNX mode and reads the identification of a device.
+pub fn identify<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+) -> Result<DeviceIdentity, LwnxError> {
+    engage_lwnx_mode(device_context)?;
+
+    Ok(DeviceIdentity {
+        product_name: device_context.read::<ProductName>()?,
+        firmware_version: device_context.read::<FirmwareVersion>()?,
+        serial_number: device_context.read::<SerialNumber>()?,
+    })
+}
+
+/// USB details of the port a device was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsbInfo {
+    pub vid: u16,
+    pub pid: u16,
+    pub serial_number: Option<String>,
+}
+
+/// A device that answered on a serial port.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    pub port_name: String,
+    /// `None` if the port is not a USB port.
+    pub usb: Option<UsbInfo>,
+    pub identity: DeviceIdentity,
+}
+
+/// Settings used while probing ports.
+pub struct Discovery {
+    pub bit_rate: u32,
+    /// Time to wait for each response, in ms. Kept short since most ports
+    /// will not answer.
+    pub command_timeout: u64,
+    pub command_retries: i32,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Discovery {
+    pub fn new() -> Discovery {
+        Discovery {
+            bit_rate: 921600,
+            command_timeout: 100,
+            command_retries: 2,
+        }
+    }
+
+    /// Probes every available serial port and returns the devices that
+    /// answered. Ports that cannot be opened or do not answer are skipped.
+    #[cfg(unix)]
+    pub fn scan(&self) -> Result<Vec<DiscoveredDevice>, LwnxError> {
+        use serialport::{available_ports, SerialPortType};
+
+        let ports = available_ports().map_err(|e| LwnxError::Platform(e.to_string()))?;
+        let mut devices = Vec::new();
+
+        for port in ports {
+            let usb = match port.port_type {
+                SerialPortType::UsbPort(info) => Some(UsbInfo {
+                    vid: info.vid,
+                    pid: info.pid,
+                    serial_number: info.serial_number,
+                }),
+                _ => None,
+            };
+
+            if let Ok(identity) = self.probe(&port.port_name) {
+                devices.push(DiscoveredDevice {
+                    port_name: port.port_name,
+                    usb,
+                    identity,
+                });
+            }
+        }
+
+        Ok(devices)
+    }
+
+    /// Opens a port and reads the identification of the device on it.
+    #[cfg(unix)]
+    pub fn probe(&self, port_name: &str) -> Result<DeviceIdentity, LwnxError> {
+        let mut port = LinuxSerialPort::new();
+        port.connect(port_name, self.bit_rate)?;
+
+        let mut device_context = DeviceContext::new(port);
+        device_context.command_timeout = self.command_timeout;
+        device_context.command_retries = self.command_retries;
+        identify(&mut device_context)
+    }
+}
@@ -0,0 +1,226 @@
+use core::ops::{BitOr, BitOrAssign};
+
+use crate::commands::{CommandData, DistanceData};
+use crate::lwnx::{self, DeviceContext, LwnxError, Response, UserPlatform};
+
+/// Bit field selecting which values the device includes in distance output.
+///
+/// Each enabled value is sent as a little endian 16 bit signed integer, in the
+/// order of the flag bits.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DistanceOutputConfig(u32);
+
+impl DistanceOutputConfig {
+    pub const FIRST_RETURN_RAW: DistanceOutputConfig = DistanceOutputConfig(1 << 0);
+    pub const FIRST_RETURN_FILTERED: DistanceOutputConfig = DistanceOutputConfig(1 << 1);
+    pub const FIRST_RETURN_STRENGTH: DistanceOutputConfig = DistanceOutputConfig(1 << 2);
+    pub const LAST_RETURN_RAW: DistanceOutputConfig = DistanceOutputConfig(1 << 3);
+    pub const LAST_RETURN_FILTERED: DistanceOutputConfig = DistanceOutputConfig(1 << 4);
+    pub const LAST_RETURN_STRENGTH: DistanceOutputConfig = DistanceOutputConfig(1 << 5);
+    pub const BACKGROUND_NOISE: DistanceOutputConfig = DistanceOutputConfig(1 << 6);
+    pub const TEMPERATURE: DistanceOutputConfig = DistanceOutputConfig(1 << 7);
+    pub const YAW_ANGLE: DistanceOutputConfig = DistanceOutputConfig(1 << 8);
+
+    /// Number of defined flag bits.
+    const FLAG_COUNT: u32 = 9;
+
+    pub const fn empty() -> DistanceOutputConfig {
+        DistanceOutputConfig(0)
+    }
+
+    pub const fn from_bits(bits: u32) -> DistanceOutputConfig {
+        DistanceOutputConfig(bits)
+    }
+
+    pub const fn bits(&self) -> u32 {
+        self.0
+    }
+
+    pub const fn contains(&self, other: DistanceOutputConfig) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: DistanceOutputConfig) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: DistanceOutputConfig) {
+        self.0 &= !other.0;
+    }
+
+    /// Size in bytes of a distance packet payload produced with this configuration.
+    pub fn payload_size(&self) -> usize {
+        (self.0 & ((1 << Self::FLAG_COUNT) - 1)).count_ones() as usize * 2
+    }
+}
+
+impl BitOr for DistanceOutputConfig {
+    type Output = DistanceOutputConfig;
+
+    fn bitor(self, rhs: DistanceOutputConfig) -> DistanceOutputConfig {
+        DistanceOutputConfig(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for DistanceOutputConfig {
+    fn bitor_assign(&mut self, rhs: DistanceOutputConfig) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl CommandData for DistanceOutputConfig {
+    fn read<T: UserPlatform>(
+        device_context: &mut DeviceContext<T>,
+        command_id: u8,
+    ) -> Result<Self, LwnxError> {
+        Ok(DistanceOutputConfig(lwnx::cmd_read_u32(
+            device_context,
+            command_id,
+        )?))
+    }
+
+    fn write<T: UserPlatform>(
+        &self,
+        device_context: &mut DeviceContext<T>,
+        command_id: u8,
+        _size: usize,
+    ) -> Result<(), LwnxError> {
+        lwnx::cmd_write_u32(device_context, command_id, self.0)
+    }
+}
+
+/// A decoded distance packet.
+///
+/// Fields are `None` when the matching flag is not enabled in the distance
+/// output configuration. Distances are in cm, strengths in percent,
+/// temperature in 1/100 degrees C and yaw angle in 1/100 degrees.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DistanceMeasurement {
+    pub first_return_raw: Option<i16>,
+    pub first_return_filtered: Option<i16>,
+    pub first_return_strength: Option<i16>,
+    pub last_return_raw: Option<i16>,
+    pub last_return_filtered: Option<i16>,
+    pub last_return_strength: Option<i16>,
+    pub background_noise: Option<i16>,
+    pub temperature: Option<i16>,
+    pub yaw_angle: Option<i16>,
+}
+
+impl DistanceMeasurement {
+    /// Decodes a distance packet payload laid out according to `config`.
+    pub fn decode(
+        config: DistanceOutputConfig,
+        payload: &[u8],
+    ) -> Result<DistanceMeasurement, LwnxError> {
+        if payload.len() < config.payload_size() {
+            return Err(LwnxError::UnexpectedResponseLength {
+                command_id: DistanceData::ID,
+                expected: config.payload_size(),
+                actual: payload.len(),
+            });
+        }
+
+        let mut measurement = DistanceMeasurement::default();
+        let mut values = payload
+            .chunks_exact(2)
+            .map(|b| i16::from_le_bytes([b[0], b[1]]));
+
+        let fields = [
+            (
+                DistanceOutputConfig::FIRST_RETURN_RAW,
+                &mut measurement.first_return_raw,
+            ),
+            (
+                DistanceOutputConfig::FIRST_RETURN_FILTERED,
+                &mut measurement.first_return_filtered,
+            ),
+            (
+                DistanceOutputConfig::FIRST_RETURN_STRENGTH,
+                &mut measurement.first_return_strength,
+            ),
+            (
+                DistanceOutputConfig::LAST_RETURN_RAW,
+                &mut measurement.last_return_raw,
+            ),
+            (
+                DistanceOutputConfig::LAST_RETURN_FILTERED,
+                &mut measurement.last_return_filtered,
+            ),
+            (
+                DistanceOutputConfig::LAST_RETURN_STRENGTH,
+                &mut measurement.last_return_strength,
+            ),
+            (
+                DistanceOutputConfig::BACKGROUND_NOISE,
+                &mut measurement.background_noise,
+            ),
+            (
+                DistanceOutputConfig::TEMPERATURE,
+                &mut measurement.temperature,
+            ),
+            (DistanceOutputConfig::YAW_ANGLE, &mut measurement.yaw_angle),
+        ];
+
+        for (flag, field) in fields {
+            if config.contains(flag) {
+                *field = values.next();
+            }
+        }
+
+        Ok(measurement)
+    }
+
+    #[cfg(feature = "std")]
+    /// Encodes the fields enabled in `config`, in the layout used by the device.
+    ///
+    /// Enabled fields that are `None` are encoded as zero.
+    pub fn encode(&self, config: DistanceOutputConfig) -> Vec<u8> {
+        let fields = [
+            (
+                DistanceOutputConfig::FIRST_RETURN_RAW,
+                self.first_return_raw,
+            ),
+            (
+                DistanceOutputConfig::FIRST_RETURN_FILTERED,
+                self.first_return_filtered,
+            ),
+            (
+                DistanceOutputConfig::FIRST_RETURN_STRENGTH,
+                self.first_return_strength,
+            ),
+            (DistanceOutputConfig::LAST_RETURN_RAW, self.last_return_raw),
+            (
+                DistanceOutputConfig::LAST_RETURN_FILTERED,
+                self.last_return_filtered,
+            ),
+            (
+                DistanceOutputConfig::LAST_RETURN_STRENGTH,
+                self.last_return_strength,
+            ),
+            (
+                DistanceOutputConfig::BACKGROUND_NOISE,
+                self.background_noise,
+            ),
+            (DistanceOutputConfig::TEMPERATURE, self.temperature),
+            (DistanceOutputConfig::YAW_ANGLE, self.yaw_angle),
+        ];
+
+        let mut payload = Vec::with_capacity(config.payload_size());
+        for (flag, value) in fields {
+            if config.contains(flag) {
+                payload.extend_from_slice(&value.unwrap_or(0).to_le_bytes());
+            }
+        }
+
+        payload
+    }
+
+    /// Decodes a distance packet received from the device.
+    pub fn from_response(
+        config: DistanceOutputConfig,
+        response: &Response,
+    ) -> Result<DistanceMeasurement, LwnxError> {
+        DistanceMeasurement::decode(config, response.payload())
+    }
+}
@@ -0,0 +1,68 @@
+//! `UserPlatform` adapter for embedded serial peripherals.
+
+use embedded_hal::delay::DelayNs;
+use embedded_io::{Read, ReadReady, Write};
+
+use crate::lwnx::{LwnxError, UserPlatform};
+
+/// Drives an LWNX device through an `embedded-io` serial port.
+///
+/// `clock` returns a monotonic time in ms and is used for command timeouts.
+pub struct EmbeddedPlatform<S, D, C> {
+    pub serial: S,
+    pub delay: D,
+    pub clock: C,
+}
+
+impl<S, D, C> EmbeddedPlatform<S, D, C>
+where
+    S: Read + ReadReady + Write,
+    D: DelayNs,
+    C: FnMut() -> u64,
+{
+    pub fn new(serial: S, delay: D, clock: C) -> EmbeddedPlatform<S, D, C> {
+        EmbeddedPlatform {
+            serial,
+            delay,
+            clock,
+        }
+    }
+}
+
+impl<S, D, C> UserPlatform for EmbeddedPlatform<S, D, C>
+where
+    S: Read + ReadReady + Write,
+    D: DelayNs,
+    C: FnMut() -> u64,
+{
+    fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        match self.serial.write_all(data) {
+            Ok(_) => Ok(data.len()),
+            Err(_) => Err(LwnxError::platform("serial write failed")),
+        }
+    }
+
+    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError> {
+        // Only read when data is waiting so a poll never blocks.
+        match self.serial.read_ready() {
+            Ok(true) => {}
+            Ok(false) => return Ok(&data[..0]),
+            Err(_) => return Err(LwnxError::platform("serial read failed")),
+        }
+
+        match self.serial.read(data) {
+            Ok(bytes_read) => Ok(&data[..bytes_read]),
+            Err(_) => Err(LwnxError::platform("serial read failed")),
+        }
+    }
+
+    fn delay_callback(&mut self, duration_ms: u64) {
+        // `delay_ms` takes a u32, so longer delays saturate.
+        let duration_ms = u32::try_from(duration_ms).unwrap_or(u32::MAX);
+        self.delay.delay_ms(duration_ms);
+    }
+
+    fn time_ms(&mut self) -> u64 {
+        (self.clock)()
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::lwnx::{LwnxError, UserPlatform};
+
+/// A fault applied to a single byte passing through a `FaultInjector`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The byte is lost.
+    Drop,
+    /// The byte and everything after it is held back for the given number of
+    /// ms, as measured by the inner platform. Delayed writes wait through the
+    /// inner platform's `delay_callback` instead.
+    Delay(u64),
+    /// The byte is delivered twice.
+    Duplicate,
+    /// The byte and everything after it in the same transfer is lost.
+    Truncate,
+    /// The given bit of the byte is inverted.
+    BitFlip(u8),
+}
+
+/// Per-byte probabilities used for random read faults.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FaultRates {
+    pub drop: f64,
+    pub duplicate: f64,
+    pub truncate: f64,
+    pub bit_flip: f64,
+}
+
+/// Small xorshift generator so random fault runs can be reproduced from a seed.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Wraps a `UserPlatform` and corrupts the bytes passing through it.
+///
+/// Faults are either scheduled at a byte index counted from the start of the
+/// read or write stream, or drawn from a seeded random generator for reads.
+pub struct FaultInjector<P: UserPlatform> {
+    pub inner: P,
+    read_schedule: BTreeMap<usize, Fault>,
+    write_schedule: BTreeMap<usize, Fault>,
+    random: Option<(Rng, FaultRates)>,
+    read_count: usize,
+    write_count: usize,
+    rx_buffer: VecDeque<u8>,
+    hold_until: Option<u64>,
+    /// Number of faults applied so far.
+    pub fault_count: usize,
+}
+
+impl<P: UserPlatform> FaultInjector<P> {
+    pub fn new(inner: P) -> FaultInjector<P> {
+        FaultInjector {
+            inner,
+            read_schedule: BTreeMap::new(),
+            write_schedule: BTreeMap::new(),
+            random: None,
+            read_count: 0,
+            write_count: 0,
+            rx_buffer: VecDeque::new(),
+            hold_until: None,
+            fault_count: 0,
+        }
+    }
+
+    /// Applies `fault` to the byte at `index` of the read stream.
+    pub fn schedule_read(&mut self, index: usize, fault: Fault) {
+        self.read_schedule.insert(index, fault);
+    }
+
+    /// Applies `fault` to the byte at `index` of the write stream.
+    pub fn schedule_write(&mut self, index: usize, fault: Fault) {
+        self.write_schedule.insert(index, fault);
+    }
+
+    /// Applies random faults to read bytes using a generator seeded with `seed`.
+    pub fn set_random(&mut self, seed: u64, rates: FaultRates) {
+        self.random = Some((Rng::new(seed), rates));
+    }
+
+    /// Number of bytes read from the inner platform so far.
+    pub fn read_count(&self) -> usize {
+        self.read_count
+    }
+
+    /// Number of bytes written by the device context so far.
+    pub fn write_count(&self) -> usize {
+        self.write_count
+    }
+
+    fn random_fault(&mut self) -> Option<Fault> {
+        let (rng, rates) = self.random.as_mut()?;
+        let mut roll = rng.next_f64();
+
+        for (rate, fault) in [
+            (rates.drop, Fault::Drop),
+            (rates.duplicate, Fault::Duplicate),
+            (rates.truncate, Fault::Truncate),
+            (rates.bit_flip, Fault::BitFlip((rng.next_u64() % 8) as u8)),
+        ] {
+            if roll < rate {
+                return Some(fault);
+            }
+            roll -= rate;
+        }
+
+        None
+    }
+
+    /// Applies `fault` to `byte`, pushing the resulting bytes to `output`.
+    /// Returns false if the rest of the transfer should be discarded.
+    fn apply(&mut self, fault: Option<Fault>, byte: u8, output: &mut VecDeque<u8>) -> bool {
+        match fault {
+            None => output.push_back(byte),
+            Some(Fault::Drop) => {}
+            Some(Fault::Delay(ms)) => {
+                self.inner.delay_callback(ms);
+                output.push_back(byte);
+            }
+            Some(Fault::Duplicate) => {
+                output.push_back(byte);
+                output.push_back(byte);
+            }
+            Some(Fault::Truncate) => return false,
+            Some(Fault::BitFlip(bit)) => output.push_back(byte ^ (1 << (bit & 7))),
+        }
+
+        true
+    }
+
+    /// Discards the bytes the inner platform currently has available.
+    fn drain_inner(&mut self) -> Result<(), LwnxError> {
+        let mut buffer = [0u8; 1024];
+        let read = self.inner.read_callback(&mut buffer)?.len();
+        self.read_count += read;
+        Ok(())
+    }
+}
+
+impl<P: UserPlatform> UserPlatform for FaultInjector<P> {
+    fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        let mut output = VecDeque::with_capacity(data.len());
+
+        for b in data {
+            let fault = self.write_schedule.remove(&self.write_count);
+            self.write_count += 1;
+
+            if fault.is_some() {
+                self.fault_count += 1;
+            }
+
+            if !self.apply(fault, *b, &mut output) {
+                break;
+            }
+        }
+
+        self.inner.write_callback(output.make_contiguous())?;
+        Ok(data.len())
+    }
+
+    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError> {
+        if self.rx_buffer.is_empty() {
+            let mut buffer = vec![0u8; data.len()];
+            let read = self.inner.read_callback(&mut buffer)?.len();
+
+            for b in &buffer[..read] {
+                let fault = match self.read_schedule.remove(&self.read_count) {
+                    Some(fault) => Some(fault),
+                    None => self.random_fault(),
+                };
+                self.read_count += 1;
+
+                if fault.is_some() {
+                    self.fault_count += 1;
+                }
+
+                if let Some(Fault::Delay(ms)) = fault {
+                    self.hold_until = Some(self.inner.time_ms() + ms);
+                    self.rx_buffer.push_back(*b);
+                } else {
+                    let mut rx_buffer = std::mem::take(&mut self.rx_buffer);
+                    let keep_going = self.apply(fault, *b, &mut rx_buffer);
+                    self.rx_buffer = rx_buffer;
+
+                    if !keep_going {
+                        self.drain_inner()?;
+                        break;
+                    }
+                }
+            }
+        }
+
+        if let Some(hold_until) = self.hold_until {
+            if self.inner.time_ms() < hold_until {
+                return Ok(&data[..0]);
+            }
+            self.hold_until = None;
+        }
+
+        let mut size = 0;
+        while size < data.len() {
+            match self.rx_buffer.pop_front() {
+                Some(b) => {
+                    data[size] = b;
+                    size += 1;
+                }
+                None => break,
+            }
+        }
+
+        Ok(&data[..size])
+    }
+
+    fn delay_callback(&mut self, duration_ms: u64) {
+        self.inner.delay_callback(duration_ms);
+    }
+
+    fn set_read_timeout_callback(&mut self, timeout_ms: u64) -> Result<(), LwnxError> {
+        self.inner.set_read_timeout_callback(timeout_ms)
+    }
+
+    fn read_timeout_callback(&mut self) -> Option<u64> {
+        self.inner.read_timeout_callback()
+    }
+
+    fn set_baud_rate_callback(&mut self, baud_rate: u32) -> Result<(), LwnxError> {
+        self.inner.set_baud_rate_callback(baud_rate)
+    }
+
+    fn time_ms(&mut self) -> u64 {
+        self.inner.time_ms()
+    }
+}
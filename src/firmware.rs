@@ -0,0 +1,172 @@
+use std::path::Path;
+
+use crate::commands::{CommitFirmware, StageFirmware};
+use crate::lwnx::{
+    create_crc, handle_managed_cmd, wait_for_device, DeviceContext, LwnxError, Response,
+    UserPlatform,
+};
+
+/// Number of image bytes sent with each stage command.
+pub const FIRMWARE_PAGE_SIZE: usize = 128;
+
+/// A raw binary firmware image, staged to the device byte for byte.
+pub struct FirmwareImage {
+    data: Vec<u8>,
+}
+
+impl FirmwareImage {
+    pub fn from_bytes(data: Vec<u8>) -> Result<FirmwareImage, LwnxError> {
+        if data.is_empty() || data.len().div_ceil(FIRMWARE_PAGE_SIZE) > u16::MAX as usize {
+            return Err(LwnxError::InvalidData);
+        }
+
+        Ok(FirmwareImage { data })
+    }
+
+    /// Loads a raw binary image file. The file contents are staged to the
+    /// device as-is, so LWF container files are rejected rather than sent
+    /// with their container bytes.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<FirmwareImage, LwnxError> {
+        let path = path.as_ref();
+        if path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("lwf"))
+        {
+            return Err(LwnxError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "LWF files are not supported, use a raw binary image",
+            )));
+        }
+
+        FirmwareImage::from_bytes(std::fs::read(path)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.data.len().div_ceil(FIRMWARE_PAGE_SIZE)
+    }
+
+    /// CRC of the whole image.
+    pub fn crc(&self) -> u16 {
+        create_crc(&self.data)
+    }
+
+    /// Returns a page of the image, padded with 0xFF up to the page size.
+    fn page(&self, index: usize) -> [u8; FIRMWARE_PAGE_SIZE] {
+        let mut page = [0xFFu8; FIRMWARE_PAGE_SIZE];
+        let start = index * FIRMWARE_PAGE_SIZE;
+        let end = (start + FIRMWARE_PAGE_SIZE).min(self.data.len());
+        page[..end - start].copy_from_slice(&self.data[start..end]);
+        page
+    }
+}
+
+/// Progress reported while updating firmware.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateProgress {
+    Staging { page: usize, page_count: usize },
+    Committing,
+    WaitingForReboot,
+    Complete,
+}
+
+/// Settings for a firmware update.
+pub struct FirmwareUpdater {
+    /// Number of times a page is re-sent when the device echo does not match.
+    pub page_retries: i32,
+    /// Maximum time to wait for the device to answer after committing, in ms.
+    pub reboot_timeout: u64,
+    /// Delay between reconnection attempts while the device reboots, in ms.
+    pub reboot_poll_interval: u64,
+}
+
+impl Default for FirmwareUpdater {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FirmwareUpdater {
+    pub fn new() -> FirmwareUpdater {
+        FirmwareUpdater {
+            page_retries: 4,
+            reboot_timeout: 10000,
+            reboot_poll_interval: 250,
+        }
+    }
+
+    /// Stages the image page by page, commits it and waits for the device to
+    /// come back in LWNX mode.
+    ///
+    /// The device restarts as soon as the image is committed, so the commit
+    /// command is not expected to be answered.
+    pub fn update<T: UserPlatform, F: FnMut(UpdateProgress)>(
+        &self,
+        device_context: &mut DeviceContext<T>,
+        image: &FirmwareImage,
+        mut progress: F,
+    ) -> Result<(), LwnxError> {
+        let page_count = image.page_count();
+
+        for page in 0..page_count {
+            progress(UpdateProgress::Staging { page, page_count });
+            self.stage_page(device_context, image, page)?;
+        }
+
+        progress(UpdateProgress::Committing);
+        device_context.send_restart::<CommitFirmware>()?;
+
+        progress(UpdateProgress::WaitingForReboot);
+        wait_for_device(
+            device_context,
+            self.reboot_timeout,
+            self.reboot_poll_interval,
+        )?;
+
+        progress(UpdateProgress::Complete);
+        Ok(())
+    }
+
+    /// Sends a single page and checks the CRC of the page echoed by the device.
+    fn stage_page<T: UserPlatform>(
+        &self,
+        device_context: &mut DeviceContext<T>,
+        image: &FirmwareImage,
+        page: usize,
+    ) -> Result<(), LwnxError> {
+        let mut data = [0u8; 2 + FIRMWARE_PAGE_SIZE];
+        data[0..2].copy_from_slice(&(page as u16).to_le_bytes());
+        data[2..].copy_from_slice(&image.page(page));
+        let page_crc = create_crc(&data);
+
+        let mut response = Response::new();
+        let mut echo_crc = 0;
+
+        for _ in 0..self.page_retries {
+            handle_managed_cmd(
+                device_context,
+                StageFirmware::ID,
+                true,
+                &data,
+                &mut response,
+            )?;
+
+            echo_crc = create_crc(response.payload());
+            if echo_crc == page_crc {
+                return Ok(());
+            }
+        }
+
+        Err(LwnxError::CrcMismatch {
+            expected: page_crc,
+            actual: echo_crc,
+        })
+    }
+}
@@ -0,0 +1,30 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+pub mod baud;
+pub mod clock;
+pub mod codec;
+pub mod commands;
+pub mod crc;
+pub mod distance;
+#[cfg(feature = "std")]
+pub mod discovery;
+#[cfg(feature = "embedded")]
+pub mod embedded;
+#[cfg(feature = "std")]
+pub mod fault_injection;
+#[cfg(feature = "std")]
+pub mod firmware;
+pub mod lwnx;
+#[cfg(feature = "tokio")]
+pub mod lwnx_async;
+#[cfg(feature = "std")]
+pub mod manager;
+#[cfg(feature = "simulator")]
+pub mod simulator;
+pub mod stream;
+
+#[cfg(all(windows, feature = "std"))]
+pub mod win32_serialport;
+
+#[cfg(all(unix, feature = "std"))]
+pub mod linux_serialport;
@@ -1,28 +1,82 @@
-use std::{io::{Read, Write}, time::Duration};
+use std::{fmt, io::{self, Read, Write}, thread, time::Duration};
 use serialport::{self, DataBits, FlowControl, Parity, SerialPort, StopBits};
 
+use crate::lwnx::{LwnxError, UserPlatform};
+
+/// Serial port errors. Failed operations keep the `io::Error` reported by the port.
 #[derive(Debug)]
 pub enum LinuxSerialPortError {
     InvalidSerialPort,
-    OpenFailed,
-    WriteFailed,
+    OpenFailed(io::Error),
+    WriteFailed(io::Error),
     DidNotWriteAllBytes,
-    ReadFailed,
+    ReadFailed(io::Error),
+    SetTimeoutFailed(io::Error),
+    SetBaudRateFailed(io::Error),
+}
+
+impl fmt::Display for LinuxSerialPortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LinuxSerialPortError::InvalidSerialPort => write!(f, "serial port is not open"),
+            LinuxSerialPortError::OpenFailed(e) => write!(f, "open failed: {e}"),
+            LinuxSerialPortError::WriteFailed(e) => write!(f, "write failed: {e}"),
+            LinuxSerialPortError::DidNotWriteAllBytes => write!(f, "did not write all bytes"),
+            LinuxSerialPortError::ReadFailed(e) => write!(f, "read failed: {e}"),
+            LinuxSerialPortError::SetTimeoutFailed(e) => write!(f, "setting the timeout failed: {e}"),
+            LinuxSerialPortError::SetBaudRateFailed(e) => write!(f, "setting the baud rate failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for LinuxSerialPortError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            LinuxSerialPortError::OpenFailed(e) | LinuxSerialPortError::WriteFailed(e)
+            | LinuxSerialPortError::ReadFailed(e) | LinuxSerialPortError::SetTimeoutFailed(e)
+            | LinuxSerialPortError::SetBaudRateFailed(e) => Some(e),
+            _ => None,
+        }
+    }
 }
 
 impl From<LinuxSerialPortError> for String {
-    fn from(v: LinuxSerialPortError) -> Self { format!("{v:?}") }
+    fn from(v: LinuxSerialPortError) -> Self { v.to_string() }
+}
+
+impl From<LinuxSerialPortError> for LwnxError {
+    fn from(v: LinuxSerialPortError) -> Self {
+        match v {
+            LinuxSerialPortError::OpenFailed(e) | LinuxSerialPortError::WriteFailed(e)
+            | LinuxSerialPortError::ReadFailed(e) | LinuxSerialPortError::SetTimeoutFailed(e)
+            | LinuxSerialPortError::SetBaudRateFailed(e) => LwnxError::Io(e),
+            LinuxSerialPortError::InvalidSerialPort => LwnxError::DeviceClosed,
+            LinuxSerialPortError::DidNotWriteAllBytes => LwnxError::Io(io::ErrorKind::WriteZero.into()),
+        }
+    }
+}
+
+/// Returns true for the errors a port reports when a read timeout expires
+/// without data, which only means nothing arrived.
+fn is_timeout(e: &io::Error) -> bool {
+    matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)
 }
 
 pub struct LinuxSerialPort {
     port: Option<Box<dyn SerialPort>>,
 }
 
+impl Default for LinuxSerialPort {
+    fn default() -> Self { Self::new() }
+}
+
 impl LinuxSerialPort {
     pub fn new() -> Self { Self { port: None } }
+    /// Wraps a port that is already open.
+    pub fn from_port(port: Box<dyn SerialPort>) -> Self { Self { port: Some(port) } }
     pub fn is_invalid(&self) -> bool { self.port.is_none() }
 
-    pub fn connect(&mut self, path: &str, bit_rate: u32) -> Result<(), String> {
+    pub fn connect(&mut self, path: &str, bit_rate: u32) -> Result<(), LinuxSerialPortError> {
         let p = serialport::new(path, bit_rate)
             .data_bits(DataBits::Eight)
             .parity(Parity::None)
@@ -30,21 +84,35 @@ impl LinuxSerialPort {
             .flow_control(FlowControl::None)
             .timeout(Duration::from_millis(10))
             .open()
-            .map_err(|_| String::from(LinuxSerialPortError::OpenFailed))?;
+            .map_err(|e| LinuxSerialPortError::OpenFailed(e.into()))?;
         self.port = Some(p);
         Ok(())
     }
 
     pub fn disconnect(&mut self) { self.port = None; }
 
+    pub fn set_timeout(&mut self, timeout_ms: u64) -> Result<(), LinuxSerialPortError> {
+        let p = self.port.as_mut().ok_or(LinuxSerialPortError::InvalidSerialPort)?;
+        p.set_timeout(Duration::from_millis(timeout_ms))
+            .map_err(|e| LinuxSerialPortError::SetTimeoutFailed(e.into()))
+    }
+
+    /// Current read timeout in ms, or `None` if the port is not open.
+    pub fn timeout(&self) -> Option<u64> { self.port.as_ref().map(|p| p.timeout().as_millis() as u64) }
+
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), LinuxSerialPortError> {
+        let p = self.port.as_mut().ok_or(LinuxSerialPortError::InvalidSerialPort)?;
+        p.set_baud_rate(baud_rate).map_err(|e| LinuxSerialPortError::SetBaudRateFailed(e.into()))
+    }
+
     pub fn write(&mut self, buffer: &[u8]) -> Result<u32, LinuxSerialPortError> {
         let p = self.port.as_mut().ok_or(LinuxSerialPortError::InvalidSerialPort)?;
         let mut total = 0;
         while total < buffer.len() {
             match p.write(&buffer[total..]) {
                 Ok(n) if n > 0 => total += n,
-                Ok(_) => return Err(LinuxSerialPortError::WriteFailed),
-                Err(_) => return Err(LinuxSerialPortError::WriteFailed),
+                Ok(_) => return Err(LinuxSerialPortError::DidNotWriteAllBytes),
+                Err(e) => return Err(LinuxSerialPortError::WriteFailed(e)),
             }
         }
         if total != buffer.len() { return Err(LinuxSerialPortError::DidNotWriteAllBytes); }
@@ -55,7 +123,88 @@ impl LinuxSerialPort {
         let p = self.port.as_mut().ok_or(LinuxSerialPortError::InvalidSerialPort)?;
         match p.read(buf) {
             Ok(n) => Ok(&buf[..n]),
-            Err(_) => Err(LinuxSerialPortError::ReadFailed),
+            Err(e) if is_timeout(&e) => Ok(&buf[..0]),
+            Err(e) => Err(LinuxSerialPortError::ReadFailed(e)),
+        }
+    }
+}
+
+
+/// Implementation example for the Rust serialport crate.
+impl UserPlatform for Box<dyn SerialPort> {
+    fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        match Write::write_all(self, data) {
+            Ok(_) => Ok(data.len()),
+            Err(e) => Err(LwnxError::Io(e)),
+        }
+    }
+
+    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError> {
+        match Read::read(self, data) {
+            Ok(bytes_read) => Ok(&data[0..bytes_read]),
+            Err(e) if is_timeout(&e) => Ok(&data[..0]),
+            Err(e) => Err(LwnxError::Io(e)),
+        }
+    }
+
+    fn delay_callback(&mut self, duration_ms: u64) {
+        thread::sleep(Duration::from_millis(duration_ms));
+    }
+
+    fn set_read_timeout_callback(&mut self, timeout_ms: u64) -> Result<(), LwnxError> {
+        match self.set_timeout(Duration::from_millis(timeout_ms)) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(LwnxError::Io(e.into())),
+        }
+    }
+
+    fn read_timeout_callback(&mut self) -> Option<u64> {
+        Some(self.timeout().as_millis() as u64)
+    }
+
+    fn set_baud_rate_callback(&mut self, baud_rate: u32) -> Result<(), LwnxError> {
+        match SerialPort::set_baud_rate(self.as_mut(), baud_rate) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(LwnxError::Io(e.into())),
+        }
+    }
+}
+
+/// Implementation example for the LightWare serial port implementation.
+impl UserPlatform for LinuxSerialPort {
+    fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        match self.write(data) {
+            Ok(bytes_written) => Ok(bytes_written as usize),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError> {
+        match self.read(data) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn delay_callback(&mut self, duration_ms: u64) {
+        thread::sleep(Duration::from_millis(duration_ms));
+    }
+
+    fn set_read_timeout_callback(&mut self, timeout_ms: u64) -> Result<(), LwnxError> {
+        match self.set_timeout(timeout_ms) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read_timeout_callback(&mut self) -> Option<u64> {
+        self.timeout()
+    }
+
+    fn set_baud_rate_callback(&mut self, baud_rate: u32) -> Result<(), LwnxError> {
+        match self.set_baud_rate(baud_rate) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into()),
         }
     }
 }
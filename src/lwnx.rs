@@ -1,64 +1,416 @@
-use std::time::Instant;
+use core::fmt;
+use core::time::Duration;
+
+#[cfg(feature = "std")]
+use crate::clock::{Clock, StdClock};
+use crate::crc::Crc16;
+
+/// String type returned by string reads. Without `std` this is a fixed
+/// capacity string.
+#[cfg(feature = "std")]
+pub type LwnxString = String;
+#[cfg(not(feature = "std"))]
+pub type LwnxString = heapless::String<MAX_STRING_SIZE>;
+
+/// Size of most string fields, such as the product name and serial number.
+pub const STRING_FIELD_SIZE: usize = 16;
+
+/// Largest string field supported by the string reads and writes.
+pub const MAX_STRING_SIZE: usize = 32;
+
+/// Copies `value` into an `LwnxString`, returning `None` if it does not fit.
+pub fn to_lwnx_string(value: &str) -> Option<LwnxString> {
+    #[cfg(feature = "std")]
+    return Some(value.to_owned());
+    #[cfg(not(feature = "std"))]
+    return LwnxString::try_from(value).ok();
+}
+
+/// Returns the bytes of a null padded string field up to the first null byte.
+fn string_field_bytes(field: &[u8]) -> &[u8] {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..end]
+}
+
+/// Decodes a null padded string field. A field without a null byte is used in
+/// full. Returns `InvalidData` if the string is not valid UTF-8.
+pub fn decode_string(field: &[u8]) -> Result<LwnxString, LwnxError> {
+    core::str::from_utf8(string_field_bytes(field))
+        .ok()
+        .and_then(to_lwnx_string)
+        .ok_or(LwnxError::InvalidData)
+}
+
+/// Decodes a null padded string field, replacing invalid UTF-8 sequences with
+/// U+FFFD. Without `std` the result is truncated to the string capacity.
+pub fn decode_string_lossy(field: &[u8]) -> LwnxString {
+    let bytes = string_field_bytes(field);
+
+    #[cfg(feature = "std")]
+    return String::from_utf8_lossy(bytes).into_owned();
+
+    #[cfg(not(feature = "std"))]
+    {
+        let mut value = LwnxString::new();
+        for chunk in bytes.utf8_chunks() {
+            let replacement = (!chunk.invalid().is_empty()).then_some(char::REPLACEMENT_CHARACTER);
+            for c in chunk.valid().chars().chain(replacement) {
+                if value.push(c).is_err() {
+                    return value;
+                }
+            }
+        }
+        value
+    }
+}
+
+/// Writes `value` into a null padded string field of `size` bytes. The string
+/// must leave room for at least one null byte.
+pub fn encode_string<'a>(
+    buffer: &'a mut [u8],
+    value: &str,
+    size: usize,
+) -> Result<&'a [u8], LwnxError> {
+    if size > buffer.len() {
+        return Err(LwnxError::PayloadTooLarge {
+            size,
+            max: buffer.len(),
+        });
+    }
+
+    let bytes = value.as_bytes();
+    if bytes.len() >= size {
+        return Err(LwnxError::PayloadTooLarge {
+            size: bytes.len(),
+            max: size.saturating_sub(1),
+        });
+    }
+
+    buffer[..size].fill(0);
+    buffer[..bytes.len()].copy_from_slice(bytes);
+    Ok(&buffer[..size])
+}
+
+/// The platform error that caused a read or write to fail.
+///
+/// Without `std` the underlying error is not retained.
+#[derive(Debug)]
+pub struct ErrorSource(#[cfg(feature = "std")] Box<LwnxError>);
+
+impl ErrorSource {
+    #[allow(unused_variables)]
+    pub fn new(error: LwnxError) -> ErrorSource {
+        #[cfg(feature = "std")]
+        return ErrorSource(Box::new(error));
+        #[cfg(not(feature = "std"))]
+        return ErrorSource();
+    }
+}
+
+impl fmt::Display for ErrorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        #[cfg(feature = "std")]
+        return write!(f, "{}", self.0);
+        #[cfg(not(feature = "std"))]
+        return write!(f, "platform error");
+    }
+}
 
 #[derive(Debug)]
 pub enum LwnxError {
-    DeviceError,
-    ReadError,
-    WriteError,
+    /// I/O error reported by the underlying transport.
+    #[cfg(feature = "std")]
+    Io(std::io::Error),
+    /// Error reported by a platform that does not use `std::io`.
+    #[cfg(feature = "std")]
+    Platform(String),
+    /// Error reported by a platform.
+    #[cfg(not(feature = "std"))]
+    Platform(&'static str),
+    /// Reading from the platform failed while handling a command.
+    ReadError {
+        command_id: u8,
+        source: ErrorSource,
+    },
+    /// Writing to the platform failed while sending a command.
+    WriteError {
+        command_id: u8,
+        source: ErrorSource,
+    },
+    InvalidData,
+    /// A response was received for a different command.
+    UnexpectedResponse {
+        expected: u8,
+        actual: u8,
+    },
+    /// A response payload did not have the size the command requires.
+    UnexpectedResponseLength {
+        command_id: u8,
+        expected: usize,
+        actual: usize,
+    },
+    /// Data did not match its CRC.
+    CrcMismatch {
+        expected: u16,
+        actual: u16,
+    },
+    /// Data is too large to be sent in a single packet.
+    PayloadTooLarge {
+        size: usize,
+        max: usize,
+    },
+    /// A buffer is too small to hold an encoded packet.
+    BufferTooSmall {
+        required: usize,
+        actual: usize,
+    },
     DeviceClosed,
-    PacketTimeout,
-    CommandRetriesExhausted,
+    /// No response was received within the timeout.
+    PacketTimeout {
+        command_id: u8,
+        elapsed: Duration,
+    },
+    /// Every attempt to send a command timed out.
+    CommandRetriesExhausted {
+        command_id: u8,
+        attempts: i32,
+        elapsed: Duration,
+    },
+    /// The device did not answer at any of the baud rates tried.
+    BaudRateNotDetected,
+    /// The baud rate is not one the device supports.
+    UnsupportedBaudRate(u32),
+}
+
+impl LwnxError {
+    /// Creates a platform error from a static message.
+    pub fn platform(message: &'static str) -> LwnxError {
+        #[cfg(feature = "std")]
+        return LwnxError::Platform(message.to_owned());
+        #[cfg(not(feature = "std"))]
+        return LwnxError::Platform(message);
+    }
+
+    /// Returns true if the error only means that no data arrived in time,
+    /// either as a `PacketTimeout` or as a read that timed out on the
+    /// platform.
+    pub fn is_timeout(&self) -> bool {
+        match self {
+            LwnxError::PacketTimeout { .. } => true,
+            #[cfg(feature = "std")]
+            LwnxError::Io(e) => matches!(
+                e.kind(),
+                std::io::ErrorKind::TimedOut | std::io::ErrorKind::WouldBlock
+            ),
+            #[cfg(feature = "std")]
+            LwnxError::ReadError { source, .. } => source.0.is_timeout(),
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for LwnxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            LwnxError::Io(e) => write!(f, "I/O error: {}", e),
+            LwnxError::Platform(s) => write!(f, "platform error: {}", s),
+            LwnxError::ReadError { command_id, source } => {
+                write!(f, "read failed for command {}: {}", command_id, source)
+            }
+            LwnxError::WriteError { command_id, source } => {
+                write!(f, "write failed for command {}: {}", command_id, source)
+            }
+            LwnxError::InvalidData => write!(f, "invalid data"),
+            LwnxError::UnexpectedResponse { expected, actual } => write!(
+                f,
+                "expected response to command {} but received command {}",
+                expected, actual
+            ),
+            LwnxError::UnexpectedResponseLength {
+                command_id,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "command {} expected {} bytes of data but received {}",
+                command_id, expected, actual
+            ),
+            LwnxError::CrcMismatch { expected, actual } => write!(
+                f,
+                "CRC mismatch: expected {:#06X}, got {:#06X}",
+                expected, actual
+            ),
+            LwnxError::PayloadTooLarge { size, max } => write!(
+                f,
+                "payload of {} bytes exceeds the maximum of {} bytes",
+                size, max
+            ),
+            LwnxError::BufferTooSmall { required, actual } => write!(
+                f,
+                "packet needs {} bytes but the buffer holds {}",
+                required, actual
+            ),
+            LwnxError::DeviceClosed => write!(f, "device closed"),
+            LwnxError::PacketTimeout {
+                command_id,
+                elapsed,
+            } => write!(
+                f,
+                "timed out waiting for command {} after {} ms",
+                command_id,
+                elapsed.as_millis()
+            ),
+            LwnxError::CommandRetriesExhausted {
+                command_id,
+                attempts,
+                elapsed,
+            } => write!(
+                f,
+                "command {} failed after {} attempts in {} ms",
+                command_id,
+                attempts,
+                elapsed.as_millis()
+            ),
+            LwnxError::BaudRateNotDetected => {
+                write!(f, "device did not answer at any of the baud rates tried")
+            }
+            LwnxError::UnsupportedBaudRate(baud_rate) => {
+                write!(f, "baud rate {} is not supported", baud_rate)
+            }
+        }
+    }
+}
+
+impl core::error::Error for LwnxError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            LwnxError::Io(e) => Some(e),
+            #[cfg(feature = "std")]
+            LwnxError::ReadError { source, .. } | LwnxError::WriteError { source, .. } => {
+                Some(source.0.as_ref())
+            }
+            _ => None,
+        }
+    }
 }
 
+#[cfg(feature = "std")]
+impl From<std::io::Error> for LwnxError {
+    fn from(value: std::io::Error) -> Self {
+        LwnxError::Io(value)
+    }
+}
+
+#[cfg(feature = "std")]
 impl From<LwnxError> for String {
     fn from(value: LwnxError) -> Self {
-        std::format!("{:?}", value)
+        value.to_string()
     }
 }
 
-/// Creates a packet CRC.
-pub fn create_crc(data: &[u8]) -> u16 {
-    let mut crc: u16 = 0;
+/// Largest data size that fits in a packet the device will accept.
+pub const MAX_DATA_SIZE: usize = 1016;
 
-    for b in data {
-        let mut code = crc >> 8;
-        code ^= *b as u16;
-        code ^= code >> 4;
-        crc = crc << 8;
-        crc ^= code;
-        code = code << 5;
-        crc ^= code;
-        code = code << 7;
-        crc ^= code;
+/// Returns the size of a packet carrying `data_size` bytes of data: start
+/// byte, flags, command id, data and CRC.
+pub const fn packet_size(data_size: usize) -> usize {
+    data_size + 6
+}
+
+/// Size of the largest packet the device will accept.
+pub const MAX_PACKET_SIZE: usize = packet_size(MAX_DATA_SIZE);
+
+// The flags store the data size plus the command id in 10 bits.
+const _: () = assert!(MAX_DATA_SIZE < (1 << 10) - 1);
+
+pub(crate) fn check_data_size(size: usize) -> Result<(), LwnxError> {
+    if size > MAX_DATA_SIZE {
+        return Err(LwnxError::PayloadTooLarge {
+            size,
+            max: MAX_DATA_SIZE,
+        });
     }
 
-    return crc;
+    Ok(())
+}
+
+/// Creates a packet CRC.
+pub fn create_crc(data: &[u8]) -> u16 {
+    Crc16::checksum(data)
 }
 
 /// Fills a buffer with bytes that describe a packet.
+///
+/// Returns `PayloadTooLarge` if the data does not fit in a packet and
+/// `BufferTooSmall` if the buffer cannot hold the packet.
 pub fn create_packet_bytes<'a>(
     buffer: &'a mut [u8],
     command_id: u8,
     write: bool,
     data: &[u8],
-) -> &'a [u8] {
+) -> Result<&'a [u8], LwnxError> {
+    check_data_size(data.len())?;
+
+    let size = packet_size(data.len());
+    if buffer.len() < size {
+        return Err(LwnxError::BufferTooSmall {
+            required: size,
+            actual: buffer.len(),
+        });
+    }
+
+    let packet = &mut buffer[..size];
+    write_packet(packet, command_id, write, data);
+    Ok(packet)
+}
+
+/// Appends the bytes of a packet to `buffer`.
+#[cfg(feature = "std")]
+pub fn encode_packet_into(
+    buffer: &mut Vec<u8>,
+    command_id: u8,
+    write: bool,
+    data: &[u8],
+) -> Result<(), LwnxError> {
+    check_data_size(data.len())?;
+
+    let start = buffer.len();
+    buffer.resize(start + packet_size(data.len()), 0);
+    write_packet(&mut buffer[start..], command_id, write, data);
+    Ok(())
+}
+
+/// Writes a packet to a slice of exactly `packet_size(data.len())` bytes.
+fn write_packet(packet: &mut [u8], command_id: u8, write: bool, data: &[u8]) {
     let data_size = data.len();
-    let payload_length = 1 + data_size as i32;
+    let payload_length = (1 + data_size) as u16;
 
     let flags: u16 = match write {
-        true => ((payload_length << 6) | (0x1)) as u16,
-        false => (payload_length << 6) as u16,
+        true => (payload_length << 6) | 0x1,
+        false => payload_length << 6,
     };
 
-    buffer[0] = 0xAA;
-    buffer[1..3].copy_from_slice(&flags.to_le_bytes());
-    buffer[3] = command_id;
-    buffer[4..4 + data_size].copy_from_slice(data);
+    packet[0] = 0xAA;
+    packet[1..3].copy_from_slice(&flags.to_le_bytes());
+    packet[3] = command_id;
+    packet[4..4 + data_size].copy_from_slice(data);
 
-    let crc = create_crc(&buffer[0..=3 + data_size]);
-    buffer[4 + data_size..6 + data_size].copy_from_slice(&crc.to_le_bytes());
+    let crc = create_crc(&packet[0..=3 + data_size]);
+    packet[4 + data_size..6 + data_size].copy_from_slice(&crc.to_le_bytes());
+}
 
-    return &buffer[0..6 + data_size];
+/// Result of feeding a byte to `Response::parse_data`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseResult {
+    /// More bytes are needed to complete the packet.
+    Incomplete,
+    /// A packet with a valid CRC has been received.
+    Complete,
+    /// A packet was received but its CRC did not match. `expected` is the CRC
+    /// calculated over the received bytes and `actual` the CRC that was sent.
+    Corrupt { expected: u16, actual: u16 },
 }
 
 enum ResponseParseState {
@@ -95,23 +447,99 @@ impl Response {
         self.data[3]
     }
 
+    /// Returns true if the packet was sent as a write command.
+    pub fn is_write(&self) -> bool {
+        self.data[1] & 0x1 != 0
+    }
+
     pub fn get_size(&self) -> i32 {
         self.size
     }
 
-    pub fn get_string_data(&self) -> Option<String> {
-        if let Ok(s) = std::str::from_utf8(&self.data[4..20]) {
-            Some(s.to_owned())
-        } else {
-            None
+    /// Returns the data bytes that follow the command id, excluding the CRC.
+    pub fn payload(&self) -> &[u8] {
+        if self.size < 6 {
+            return &[];
+        }
+
+        &self.data[4..(self.size - 2) as usize]
+    }
+
+    /// Returns the string field at the start of the payload.
+    pub fn get_string_data(&self) -> Option<LwnxString> {
+        self.read_string(0, STRING_FIELD_SIZE).ok()
+    }
+
+    pub fn get_uint32_data(&self) -> Result<u32, LwnxError> {
+        self.read_u32(0)
+    }
+
+    /// Returns `len` payload bytes starting at `offset`.
+    pub fn read_bytes(&self, offset: usize, len: usize) -> Result<&[u8], LwnxError> {
+        let payload = self.payload();
+        match offset.checked_add(len) {
+            Some(end) if end <= payload.len() => Ok(&payload[offset..end]),
+            _ => Err(LwnxError::UnexpectedResponseLength {
+                command_id: self.get_command(),
+                expected: offset.saturating_add(len),
+                actual: payload.len(),
+            }),
         }
     }
 
-    pub fn get_uint32_data(&self) -> u32 {
-        u32::from_le_bytes(self.data[4..8].try_into().unwrap())
+    /// Copies `N` payload bytes starting at `offset` into an array.
+    pub fn read_array<const N: usize>(&self, offset: usize) -> Result<[u8; N], LwnxError> {
+        let mut bytes = [0u8; N];
+        bytes.copy_from_slice(self.read_bytes(offset, N)?);
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&self, offset: usize) -> Result<u8, LwnxError> {
+        Ok(u8::from_le_bytes(self.read_array(offset)?))
+    }
+
+    pub fn read_i8(&self, offset: usize) -> Result<i8, LwnxError> {
+        Ok(i8::from_le_bytes(self.read_array(offset)?))
+    }
+
+    pub fn read_u16(&self, offset: usize) -> Result<u16, LwnxError> {
+        Ok(u16::from_le_bytes(self.read_array(offset)?))
+    }
+
+    pub fn read_i16(&self, offset: usize) -> Result<i16, LwnxError> {
+        Ok(i16::from_le_bytes(self.read_array(offset)?))
     }
 
-    pub fn parse_data(&mut self, data: u8) -> bool {
+    pub fn read_u32(&self, offset: usize) -> Result<u32, LwnxError> {
+        Ok(u32::from_le_bytes(self.read_array(offset)?))
+    }
+
+    pub fn read_i32(&self, offset: usize) -> Result<i32, LwnxError> {
+        Ok(i32::from_le_bytes(self.read_array(offset)?))
+    }
+
+    pub fn read_f32(&self, offset: usize) -> Result<f32, LwnxError> {
+        Ok(f32::from_le_bytes(self.read_array(offset)?))
+    }
+
+    /// Reads a `len` byte string field starting at `offset`. The string ends at
+    /// the first null byte or at the end of the field.
+    pub fn read_string(&self, offset: usize, len: usize) -> Result<LwnxString, LwnxError> {
+        decode_string(self.read_bytes(offset, len)?)
+    }
+
+    /// Reads a `len` byte string field, replacing invalid UTF-8 sequences.
+    pub fn read_string_lossy(&self, offset: usize, len: usize) -> Result<LwnxString, LwnxError> {
+        Ok(decode_string_lossy(self.read_bytes(offset, len)?))
+    }
+
+    /// Returns true if the parser is waiting for the start of a packet.
+    pub fn is_idle(&self) -> bool {
+        matches!(self.parse_state, ResponseParseState::StartByte)
+    }
+
+    /// Feeds a single byte to the packet parser.
+    pub fn parse_data(&mut self, data: u8) -> ParseResult {
         match self.parse_state {
             ResponseParseState::StartByte => {
                 if data == 0xAA {
@@ -146,27 +574,168 @@ impl Response {
                     let verify_crc = create_crc(&self.data[..(self.size - 2) as usize]);
 
                     if crc == verify_crc {
-                        return true;
+                        return ParseResult::Complete;
                     } else {
-                        println!("Packet has invalid CRC");
+                        return ParseResult::Corrupt {
+                            expected: verify_crc,
+                            actual: crc,
+                        };
                     }
                 }
             }
         }
-        return false;
+        ParseResult::Incomplete
+    }
+}
+
+impl Default for Response {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 pub trait UserPlatform {
     fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError>;
+
+    /// Reads into `data` and returns the bytes read. `data` is the whole
+    /// receive buffer, so the read should return as soon as any bytes are
+    /// available rather than wait for it to fill.
     fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError>;
     fn delay_callback(&mut self, duration_ms: u64);
+
+    /// Sets how long `read_callback` may block waiting for data. Only called
+    /// with `PollStrategy::Blocking`; platforms that cannot block ignore it.
+    fn set_read_timeout_callback(&mut self, _timeout_ms: u64) -> Result<(), LwnxError> {
+        Ok(())
+    }
+
+    /// Returns the current read timeout in ms, so it can be restored after a
+    /// blocking receive. Platforms without one return `None`.
+    fn read_timeout_callback(&mut self) -> Option<u64> {
+        None
+    }
+
+    /// Changes the baud rate of the link. Platforms that cannot change it
+    /// return an error.
+    fn set_baud_rate_callback(&mut self, _baud_rate: u32) -> Result<(), LwnxError> {
+        Err(LwnxError::platform(
+            "changing the baud rate is not supported",
+        ))
+    }
+
+    /// Returns a monotonic time in ms, used to measure timeouts.
+    #[cfg(feature = "std")]
+    fn time_ms(&mut self) -> u64 {
+        StdClock.now_ms()
+    }
+
+    /// Returns a monotonic time in ms, used to measure timeouts.
+    #[cfg(not(feature = "std"))]
+    fn time_ms(&mut self) -> u64;
+}
+
+/// Counters describing the health of the link to a device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LinkStats {
+    /// Packets rejected because of an invalid CRC.
+    pub crc_errors: u64,
+    /// Times the parser abandoned a packet and searched for the next start byte.
+    pub resyncs: u64,
+    /// Bytes discarded while searching for a start byte.
+    pub dropped_bytes: u64,
+}
+
+impl LinkStats {
+    /// Feeds a byte to `response` and records any framing problems.
+    pub fn parse(&mut self, response: &mut Response, data: u8) -> ParseResult {
+        let was_idle = response.is_idle();
+        let result = response.parse_data(data);
+
+        match result {
+            ParseResult::Complete => {}
+            ParseResult::Corrupt { .. } => {
+                self.crc_errors += 1;
+                self.resyncs += 1;
+            }
+            ParseResult::Incomplete => {
+                if was_idle && response.is_idle() {
+                    self.dropped_bytes += 1;
+                } else if response.is_idle() {
+                    // The packet header had an invalid length.
+                    self.resyncs += 1;
+                }
+            }
+        }
+
+        result
+    }
+}
+
+/// Size of the buffer used to read from the platform.
+const RX_BUFFER_SIZE: usize = 256;
+
+/// Bytes read from the platform that have not been parsed yet.
+///
+/// Bytes left over once a packet is complete are kept for the next receive.
+pub(crate) struct RxBuffer {
+    data: [u8; RX_BUFFER_SIZE],
+    start: usize,
+    end: usize,
+}
+
+impl RxBuffer {
+    pub(crate) fn new() -> RxBuffer {
+        RxBuffer {
+            data: [0u8; RX_BUFFER_SIZE],
+            start: 0,
+            end: 0,
+        }
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Returns the whole buffer for refilling. Only valid once empty.
+    pub(crate) fn space(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    /// Marks the first `size` bytes of the buffer as filled.
+    pub(crate) fn filled(&mut self, size: usize) {
+        self.start = 0;
+        self.end = size.min(RX_BUFFER_SIZE);
+    }
+
+    pub(crate) fn pop(&mut self) -> Option<u8> {
+        if self.is_empty() {
+            return None;
+        }
+
+        self.start += 1;
+        Some(self.data[self.start - 1])
+    }
+}
+
+/// How `recv_packet` waits for data from the platform.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PollStrategy {
+    /// Read again immediately when no data is available.
+    Busy,
+    /// Call `delay_callback` with the given ms when no data is available.
+    Sleep(u64),
+    /// Let `read_callback` block until data arrives or the remaining timeout,
+    /// set through `set_read_timeout_callback`, expires.
+    Blocking,
 }
 
 pub struct DeviceContext<T: UserPlatform> {
     pub user_platform: T,
     pub command_timeout: u64,
     pub command_retries: i32,
+    pub poll_strategy: PollStrategy,
+    pub link_stats: LinkStats,
+    rx_buffer: RxBuffer,
 }
 
 impl<T: UserPlatform> DeviceContext<T> {
@@ -175,8 +744,16 @@ impl<T: UserPlatform> DeviceContext<T> {
             user_platform,
             command_timeout: 500,
             command_retries: 4,
+            poll_strategy: PollStrategy::Sleep(1),
+            link_stats: LinkStats::default(),
+            rx_buffer: RxBuffer::new(),
         }
     }
+
+    /// Milliseconds since `start_time`, as measured by the platform.
+    pub(crate) fn elapsed_ms(&mut self, start_time: u64) -> u64 {
+        self.user_platform.time_ms().saturating_sub(start_time)
+    }
 }
 
 /// Sends a command 0 packet to alert the device that LWNX mode is required.
@@ -185,64 +762,174 @@ impl<T: UserPlatform> DeviceContext<T> {
 pub fn engage_lwnx_mode<T: UserPlatform>(
     device_context: &mut DeviceContext<T>,
 ) -> Result<(), LwnxError> {
-    let mut packet_buffer = [0u8; 1024];
-    let packet_bytes = create_packet_bytes(&mut packet_buffer, 0, false, &[]);
+    let mut packet_buffer = [0u8; packet_size(0)];
+    let packet_bytes = create_packet_bytes(&mut packet_buffer, 0, false, &[])?;
 
-    match cmd_write(device_context, packet_bytes) {
+    match cmd_write(device_context, 0, packet_bytes) {
         Ok(_) => Ok(()),
         Err(s) => Err(s),
     }
 }
 
+/// Sends a command packet without waiting for a response.
+///
+/// Used for commands that restart the device before it can reply.
+pub fn send_cmd<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    write: bool,
+    data: &[u8],
+) -> Result<(), LwnxError> {
+    let mut packet_buffer = [0u8; MAX_PACKET_SIZE];
+    let packet_bytes = create_packet_bytes(&mut packet_buffer, command_id, write, data)?;
+    cmd_write(device_context, command_id, packet_bytes)?;
+    Ok(())
+}
+
+/// Polls the device until it answers a product name read, re-engaging LWNX
+/// mode before every attempt. Used while a device restarts.
+pub fn wait_for_device<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    timeout: u64,
+    poll_interval: u64,
+) -> Result<(), LwnxError> {
+    let start_time = device_context.user_platform.time_ms();
+
+    while device_context.elapsed_ms(start_time) < timeout {
+        device_context.user_platform.delay_callback(poll_interval);
+
+        if engage_lwnx_mode(device_context).is_ok() && cmd_read_string(device_context, 0).is_ok() {
+            return Ok(());
+        }
+    }
+
+    Err(LwnxError::PacketTimeout {
+        command_id: 0,
+        elapsed: Duration::from_millis(device_context.elapsed_ms(start_time)),
+    })
+}
+
+/// Reads whatever data the platform has while handling `command_id`.
 pub fn cmd_read<'a, T: UserPlatform>(
     platform: &mut DeviceContext<T>,
+    command_id: u8,
     buffer: &'a mut [u8],
 ) -> Result<&'a [u8], LwnxError> {
-    let read_result = platform.user_platform.read_callback(buffer);
-    if let Ok(s) = read_result {
-        return Ok(s);
-    }
-
-    Err(LwnxError::ReadError)
+    platform
+        .user_platform
+        .read_callback(buffer)
+        .map_err(|e| LwnxError::ReadError {
+            command_id,
+            source: ErrorSource::new(e),
+        })
 }
 
+/// Writes an encoded packet for `command_id` to the platform.
 pub fn cmd_write<T: UserPlatform>(
     device_context: &mut DeviceContext<T>,
+    command_id: u8,
     buffer: &[u8],
 ) -> Result<usize, LwnxError> {
-    if let Ok(s) = device_context.user_platform.write_callback(buffer) {
-        return Ok(s);
-    }
-
-    Err(LwnxError::WriteError)
+    device_context
+        .user_platform
+        .write_callback(buffer)
+        .map_err(|e| LwnxError::WriteError {
+            command_id,
+            source: ErrorSource::new(e),
+        })
 }
 
+/// Waits up to `timeout` ms for a packet with `command_id`.
+///
+/// With `PollStrategy::Blocking` the platform read timeout is changed while
+/// waiting and restored afterwards.
 pub fn recv_packet<T: UserPlatform>(
     device_context: &mut DeviceContext<T>,
     command_id: u8,
     response: &mut Response,
     timeout: u64,
 ) -> Result<(), LwnxError> {
-    let mut byte = [0u8];
+    let previous_timeout = match device_context.poll_strategy {
+        PollStrategy::Blocking => device_context.user_platform.read_timeout_callback(),
+        _ => None,
+    };
+
+    let result = wait_for_packet(device_context, command_id, response, timeout);
+
+    if let Some(timeout_ms) = previous_timeout {
+        let restored = device_context
+            .user_platform
+            .set_read_timeout_callback(timeout_ms)
+            .map_err(|e| LwnxError::ReadError {
+                command_id,
+                source: ErrorSource::new(e),
+            });
+        return result.and(restored);
+    }
 
+    result
+}
+
+fn wait_for_packet<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    response: &mut Response,
+    timeout: u64,
+) -> Result<(), LwnxError> {
     response.reset();
 
-    let instant_time = Instant::now();
-    let timeout_time = instant_time.elapsed().as_millis() as u64 + timeout;
+    let start_time = device_context.user_platform.time_ms();
 
-    while (instant_time.elapsed().as_millis() as u64) < timeout_time {
-        let byte_read = cmd_read(device_context, &mut byte[..])?;
+    loop {
+        while let Some(byte) = device_context.rx_buffer.pop() {
+            if device_context.link_stats.parse(response, byte) == ParseResult::Complete
+                && response.get_command() == command_id
+            {
+                return Ok(());
+            }
+        }
 
-        if byte_read.len() > 0 {
-            if response.parse_data(byte_read[0]) {
-                if response.get_command() == command_id {
-                    return Ok(());
-                }
+        let elapsed = device_context.elapsed_ms(start_time);
+        if elapsed >= timeout {
+            break;
+        }
+
+        if device_context.poll_strategy == PollStrategy::Blocking {
+            device_context
+                .user_platform
+                .set_read_timeout_callback(timeout - elapsed)
+                .map_err(|e| LwnxError::ReadError {
+                    command_id,
+                    source: ErrorSource::new(e),
+                })?;
+        }
+
+        // Platforms that report an empty read as a timed out read have no
+        // data yet.
+        let bytes_read = match device_context
+            .user_platform
+            .read_callback(device_context.rx_buffer.space())
+        {
+            Ok(bytes) => bytes.len(),
+            Err(e) if e.is_timeout() => 0,
+            Err(e) => {
+                return Err(LwnxError::ReadError {
+                    command_id,
+                    source: ErrorSource::new(e),
+                })
             }
+        };
+        device_context.rx_buffer.filled(bytes_read);
+
+        if let (0, PollStrategy::Sleep(duration_ms)) = (bytes_read, device_context.poll_strategy) {
+            device_context.user_platform.delay_callback(duration_ms);
         }
     }
 
-    Err(LwnxError::PacketTimeout)
+    Err(LwnxError::PacketTimeout {
+        command_id,
+        elapsed: Duration::from_millis(device_context.elapsed_ms(start_time)),
+    })
 }
 
 pub fn handle_managed_cmd<T: UserPlatform>(
@@ -252,11 +939,12 @@ pub fn handle_managed_cmd<T: UserPlatform>(
     write_data: &[u8],
     response: &mut Response,
 ) -> Result<(), LwnxError> {
-    let mut packet_buffer = [0u8; 1024];
-    let packet_bytes = create_packet_bytes(&mut packet_buffer, command_id, write, write_data);
+    let mut packet_buffer = [0u8; MAX_PACKET_SIZE];
+    let packet_bytes = create_packet_bytes(&mut packet_buffer, command_id, write, write_data)?;
+    let start_time = device_context.user_platform.time_ms();
 
     for _ in 0..device_context.command_retries {
-        cmd_write(device_context, packet_bytes)?;
+        cmd_write(device_context, command_id, packet_bytes)?;
         match recv_packet(
             device_context,
             command_id,
@@ -264,12 +952,16 @@ pub fn handle_managed_cmd<T: UserPlatform>(
             device_context.command_timeout,
         ) {
             Ok(_) => return Ok(()),
-            Err(LwnxError::PacketTimeout) => continue,
+            Err(LwnxError::PacketTimeout { .. }) => continue,
             Err(e) => return Err(e),
         }
     }
 
-    Err(LwnxError::CommandRetriesExhausted)
+    Err(LwnxError::CommandRetriesExhausted {
+        command_id,
+        attempts: device_context.command_retries,
+        elapsed: Duration::from_millis(device_context.elapsed_ms(start_time)),
+    })
 }
 
 pub fn cmd_read_i8<T: UserPlatform>(
@@ -278,7 +970,7 @@ pub fn cmd_read_i8<T: UserPlatform>(
 ) -> Result<i8, LwnxError> {
     let mut response = Response::new();
     handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
-    Ok(response.data[4] as i8)
+    response.read_i8(0)
 }
 
 pub fn cmd_read_i16<T: UserPlatform>(
@@ -287,7 +979,7 @@ pub fn cmd_read_i16<T: UserPlatform>(
 ) -> Result<i16, LwnxError> {
     let mut response = Response::new();
     handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
-    Ok(i16::from_le_bytes(response.data[4..6].try_into().unwrap()))
+    response.read_i16(0)
 }
 
 pub fn cmd_read_i32<T: UserPlatform>(
@@ -296,7 +988,7 @@ pub fn cmd_read_i32<T: UserPlatform>(
 ) -> Result<i32, LwnxError> {
     let mut response = Response::new();
     handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
-    Ok(i32::from_le_bytes(response.data[4..8].try_into().unwrap()))
+    response.read_i32(0)
 }
 
 pub fn cmd_read_u8<T: UserPlatform>(
@@ -305,7 +997,7 @@ pub fn cmd_read_u8<T: UserPlatform>(
 ) -> Result<u8, LwnxError> {
     let mut response = Response::new();
     handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
-    Ok(response.data[4])
+    response.read_u8(0)
 }
 
 pub fn cmd_read_u16<T: UserPlatform>(
@@ -314,7 +1006,7 @@ pub fn cmd_read_u16<T: UserPlatform>(
 ) -> Result<u16, LwnxError> {
     let mut response = Response::new();
     handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
-    Ok(u16::from_le_bytes(response.data[4..6].try_into().unwrap()))
+    response.read_u16(0)
 }
 
 pub fn cmd_read_u32<T: UserPlatform>(
@@ -323,27 +1015,28 @@ pub fn cmd_read_u32<T: UserPlatform>(
 ) -> Result<u32, LwnxError> {
     let mut response = Response::new();
     handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
-    Ok(u32::from_le_bytes(response.data[4..8].try_into().unwrap()))
+    response.read_u32(0)
 }
 
+/// Reads a string command. The string spans the whole payload, up to the first
+/// null byte, so fields of any size are supported.
 pub fn cmd_read_string<T: UserPlatform>(
     device_context: &mut DeviceContext<T>,
     command_id: u8,
-) -> Result<String, LwnxError> {
+) -> Result<LwnxString, LwnxError> {
     let mut response = Response::new();
     handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
+    decode_string(response.payload())
+}
 
-    let mut str_len = 0;
-    for (index, c) in response.data[4..20].iter().enumerate() {
-        if *c == 0 {
-            str_len = index;
-            break;
-        }
-    }
-
-    Ok(std::str::from_utf8(&response.data[4..4 + str_len])
-        .unwrap()
-        .to_owned())
+/// Reads a string command, replacing invalid UTF-8 sequences instead of failing.
+pub fn cmd_read_string_lossy<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+) -> Result<LwnxString, LwnxError> {
+    let mut response = Response::new();
+    handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
+    Ok(decode_string_lossy(response.payload()))
 }
 
 pub fn cmd_read_data<T: UserPlatform>(
@@ -353,6 +1046,100 @@ pub fn cmd_read_data<T: UserPlatform>(
 ) -> Result<(), LwnxError> {
     let mut response = Response::new();
     handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
-    buffer.copy_from_slice(&response.data[4..4 + buffer.len()]);
+    buffer.copy_from_slice(response.read_bytes(0, buffer.len())?);
     Ok(())
 }
+
+/// Sends a write command and waits for the device to echo it.
+///
+/// `recv_packet` only accepts a response to the same command, so a successful
+/// return means the device acknowledged the write.
+fn handle_write_cmd<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    data: &[u8],
+) -> Result<(), LwnxError> {
+    let mut response = Response::new();
+    handle_managed_cmd(device_context, command_id, true, data, &mut response)
+}
+
+pub fn cmd_write_i8<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    value: i8,
+) -> Result<(), LwnxError> {
+    handle_write_cmd(device_context, command_id, &value.to_le_bytes())
+}
+
+pub fn cmd_write_i16<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    value: i16,
+) -> Result<(), LwnxError> {
+    handle_write_cmd(device_context, command_id, &value.to_le_bytes())
+}
+
+pub fn cmd_write_i32<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    value: i32,
+) -> Result<(), LwnxError> {
+    handle_write_cmd(device_context, command_id, &value.to_le_bytes())
+}
+
+pub fn cmd_write_u8<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    value: u8,
+) -> Result<(), LwnxError> {
+    handle_write_cmd(device_context, command_id, &value.to_le_bytes())
+}
+
+pub fn cmd_write_u16<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    value: u16,
+) -> Result<(), LwnxError> {
+    handle_write_cmd(device_context, command_id, &value.to_le_bytes())
+}
+
+pub fn cmd_write_u32<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    value: u32,
+) -> Result<(), LwnxError> {
+    handle_write_cmd(device_context, command_id, &value.to_le_bytes())
+}
+
+/// Writes a string to a 16 byte string field, padding it with null bytes.
+///
+/// The string must leave room for a null terminator, so it can be at most 15
+/// bytes long. Use `cmd_write_string_sized` for fields of other sizes.
+pub fn cmd_write_string<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    value: &str,
+) -> Result<(), LwnxError> {
+    cmd_write_string_sized(device_context, command_id, value, STRING_FIELD_SIZE)
+}
+
+/// Writes a string to a string field of `size` bytes, padding it with null
+/// bytes. The string can be at most `size - 1` bytes long.
+pub fn cmd_write_string_sized<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    value: &str,
+    size: usize,
+) -> Result<(), LwnxError> {
+    let mut data = [0u8; MAX_STRING_SIZE];
+    let data = encode_string(&mut data, value, size)?;
+    handle_write_cmd(device_context, command_id, data)
+}
+
+pub fn cmd_write_data<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    command_id: u8,
+    data: &[u8],
+) -> Result<(), LwnxError> {
+    handle_write_cmd(device_context, command_id, data)
+}
@@ -0,0 +1,274 @@
+//! Async LWNX client built on tokio.
+//!
+//! Mirrors the blocking functions in [`crate::lwnx`] so many devices can be
+//! driven from a single runtime.
+
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+use crate::lwnx::{
+    create_packet_bytes, decode_string, decode_string_lossy, packet_size, ErrorSource, LinkStats,
+    LwnxError, ParseResult, Response, RxBuffer, MAX_PACKET_SIZE,
+};
+
+/// Async equivalent of [`crate::lwnx::UserPlatform`].
+pub trait AsyncUserPlatform: Send {
+    fn write_callback(
+        &mut self,
+        data: &[u8],
+    ) -> impl Future<Output = Result<usize, LwnxError>> + Send;
+
+    /// Reads available bytes into `data` and returns the number of bytes read.
+    fn read_callback(
+        &mut self,
+        data: &mut [u8],
+    ) -> impl Future<Output = Result<usize, LwnxError>> + Send;
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin + Send> AsyncUserPlatform for S {
+    async fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        self.write_all(data).await?;
+        self.flush().await?;
+        Ok(data.len())
+    }
+
+    async fn read_callback(&mut self, data: &mut [u8]) -> Result<usize, LwnxError> {
+        Ok(self.read(data).await?)
+    }
+}
+
+pub struct AsyncDeviceContext<T: AsyncUserPlatform> {
+    pub user_platform: T,
+    pub command_timeout: u64,
+    pub command_retries: i32,
+    pub link_stats: LinkStats,
+    rx_buffer: RxBuffer,
+}
+
+impl<T: AsyncUserPlatform> AsyncDeviceContext<T> {
+    pub fn new(user_platform: T) -> AsyncDeviceContext<T> {
+        AsyncDeviceContext {
+            user_platform,
+            command_timeout: 500,
+            command_retries: 4,
+            link_stats: LinkStats::default(),
+            rx_buffer: RxBuffer::new(),
+        }
+    }
+}
+
+/// Sends a command 0 packet to alert the device that LWNX mode is required.
+///
+/// **Note**: Does not consume any packet response if there is one.
+pub async fn engage_lwnx_mode<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+) -> Result<(), LwnxError> {
+    let mut packet_buffer = [0u8; packet_size(0)];
+    let packet_bytes = create_packet_bytes(&mut packet_buffer, 0, false, &[])?;
+
+    cmd_write(device_context, 0, packet_bytes).await?;
+    Ok(())
+}
+
+/// Reads whatever data the platform has while handling `command_id`.
+pub async fn cmd_read<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+    buffer: &mut [u8],
+) -> Result<usize, LwnxError> {
+    device_context
+        .user_platform
+        .read_callback(buffer)
+        .await
+        .map_err(|e| LwnxError::ReadError {
+            command_id,
+            source: ErrorSource::new(e),
+        })
+}
+
+/// Writes an encoded packet for `command_id` to the platform.
+pub async fn cmd_write<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+    buffer: &[u8],
+) -> Result<usize, LwnxError> {
+    device_context
+        .user_platform
+        .write_callback(buffer)
+        .await
+        .map_err(|e| LwnxError::WriteError {
+            command_id,
+            source: ErrorSource::new(e),
+        })
+}
+
+pub async fn recv_packet<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+    response: &mut Response,
+    timeout: u64,
+) -> Result<(), LwnxError> {
+    let instant_time = Instant::now();
+
+    response.reset();
+
+    let receive = async {
+        loop {
+            if device_context.rx_buffer.is_empty() {
+                let bytes_read = device_context
+                    .user_platform
+                    .read_callback(device_context.rx_buffer.space())
+                    .await
+                    .map_err(|e| LwnxError::ReadError {
+                        command_id,
+                        source: ErrorSource::new(e),
+                    })?;
+
+                if bytes_read == 0 {
+                    return Err(LwnxError::DeviceClosed);
+                }
+
+                device_context.rx_buffer.filled(bytes_read);
+            }
+
+            while let Some(byte) = device_context.rx_buffer.pop() {
+                if device_context.link_stats.parse(response, byte) == ParseResult::Complete
+                    && response.get_command() == command_id
+                {
+                    return Ok(());
+                }
+            }
+        }
+    };
+
+    match tokio::time::timeout(Duration::from_millis(timeout), receive).await {
+        Ok(result) => result,
+        Err(_) => Err(LwnxError::PacketTimeout {
+            command_id,
+            elapsed: instant_time.elapsed(),
+        }),
+    }
+}
+
+pub async fn handle_managed_cmd<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+    write: bool,
+    write_data: &[u8],
+    response: &mut Response,
+) -> Result<(), LwnxError> {
+    let mut packet_buffer = [0u8; MAX_PACKET_SIZE];
+    let packet_bytes = create_packet_bytes(&mut packet_buffer, command_id, write, write_data)?;
+    let instant_time = Instant::now();
+
+    for _ in 0..device_context.command_retries {
+        cmd_write(device_context, command_id, packet_bytes).await?;
+        let timeout = device_context.command_timeout;
+        match recv_packet(device_context, command_id, response, timeout).await {
+            Ok(_) => return Ok(()),
+            Err(LwnxError::PacketTimeout { .. }) => continue,
+            Err(e) => return Err(e),
+        }
+    }
+
+    Err(LwnxError::CommandRetriesExhausted {
+        command_id,
+        attempts: device_context.command_retries,
+        elapsed: instant_time.elapsed(),
+    })
+}
+
+/// Reads a command and returns the first `N` bytes of its data.
+async fn read_bytes<const N: usize, T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+) -> Result<[u8; N], LwnxError> {
+    let mut response = Response::new();
+    handle_managed_cmd(device_context, command_id, false, &[], &mut response).await?;
+    response.read_array(0)
+}
+
+pub async fn cmd_read_i8<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+) -> Result<i8, LwnxError> {
+    Ok(i8::from_le_bytes(
+        read_bytes(device_context, command_id).await?,
+    ))
+}
+
+pub async fn cmd_read_i16<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+) -> Result<i16, LwnxError> {
+    Ok(i16::from_le_bytes(
+        read_bytes(device_context, command_id).await?,
+    ))
+}
+
+pub async fn cmd_read_i32<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+) -> Result<i32, LwnxError> {
+    Ok(i32::from_le_bytes(
+        read_bytes(device_context, command_id).await?,
+    ))
+}
+
+pub async fn cmd_read_u8<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+) -> Result<u8, LwnxError> {
+    Ok(u8::from_le_bytes(
+        read_bytes(device_context, command_id).await?,
+    ))
+}
+
+pub async fn cmd_read_u16<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+) -> Result<u16, LwnxError> {
+    Ok(u16::from_le_bytes(
+        read_bytes(device_context, command_id).await?,
+    ))
+}
+
+pub async fn cmd_read_u32<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+) -> Result<u32, LwnxError> {
+    Ok(u32::from_le_bytes(
+        read_bytes(device_context, command_id).await?,
+    ))
+}
+
+pub async fn cmd_read_string<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+) -> Result<String, LwnxError> {
+    let mut response = Response::new();
+    handle_managed_cmd(device_context, command_id, false, &[], &mut response).await?;
+    decode_string(response.payload())
+}
+
+pub async fn cmd_read_string_lossy<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+) -> Result<String, LwnxError> {
+    let mut response = Response::new();
+    handle_managed_cmd(device_context, command_id, false, &[], &mut response).await?;
+    Ok(decode_string_lossy(response.payload()))
+}
+
+pub async fn cmd_read_data<T: AsyncUserPlatform>(
+    device_context: &mut AsyncDeviceContext<T>,
+    command_id: u8,
+    buffer: &mut [u8],
+) -> Result<(), LwnxError> {
+    let mut response = Response::new();
+    handle_managed_cmd(device_context, command_id, false, &[], &mut response).await?;
+    buffer.copy_from_slice(response.read_bytes(0, buffer.len())?);
+    Ok(())
+}
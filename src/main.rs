@@ -1,60 +1,55 @@
-use std::{thread, time::Duration};
-use std::sync::WaitTimeoutResult;
-use serialport::{available_ports, SerialPort, SerialPortType};
+use std::{env, process, thread, time::Duration};
 
-mod lwnx;
+use lw_lwnx::baud::{self, BAUD_RATES};
+use lw_lwnx::commands::{
+    DistanceOutput, FirmwareVersion, HardwareVersion, ProductName, SerialNumber, Stream, Token,
+    UserData,
+};
+#[cfg(unix)]
+use lw_lwnx::discovery::Discovery;
+use lw_lwnx::distance::{DistanceMeasurement, DistanceOutputConfig};
+use lw_lwnx::firmware::{FirmwareImage, FirmwareUpdater, UpdateProgress};
+use lw_lwnx::lwnx::{self, DeviceContext, LwnxError, Response};
+use lw_lwnx::stream;
 
 #[cfg(windows)]
-mod win32_serialport;
-#[cfg(windows)]
-use win32_serialport::WinSerialPort as SerialPort;
+use lw_lwnx::win32_serialport::WinSerialPort as MySerialPort;
 
 #[cfg(unix)]
-mod linux_serialport;
-#[cfg(unix)]
-use linux_serialport::LinuxSerialPort as MySerialPort;
+use lw_lwnx::linux_serialport::LinuxSerialPort as MySerialPort;
 
-/// Implementation example for the Rust serialport crate.
-impl lwnx::UserPlatform for Box<dyn SerialPort> {
-    fn write_callback(&mut self, data: &[u8]) -> Result<usize, lwnx::LwnxError> {
-        match self.write(data) {
-            Ok(bytes_written) => Ok(bytes_written),
-            Err(_) => Err(lwnx::LwnxError::DeviceError),
-        }
-    }
+#[cfg(windows)]
+const DEFAULT_PORT: &str = "COM5";
 
-    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], lwnx::LwnxError> {
-        match self.read(data) {
-            Ok(bytes_read) => Ok(&data[0..bytes_read]),
-            Err(_) => Err(lwnx::LwnxError::DeviceError),
-        }
-    }
+#[cfg(unix)]
+const DEFAULT_PORT: &str = "/dev/ttyACM0";
 
-    fn delay_callback(&mut self, duration_ms: u64) {
-        thread::sleep(Duration::from_millis(duration_ms));
-    }
-}
+const DEFAULT_BAUD: u32 = 921600;
 
-/// Implementation example for the LightWare serial port implementation.
-impl lwnx::UserPlatform for MySerialPort {
-    fn write_callback(&mut self, data: &[u8]) -> Result<usize, lwnx::LwnxError> {
-        match self.write(data) {
-            Ok(bytes_written) => Ok(bytes_written as usize),
-            Err(_) => Err(lwnx::LwnxError::DeviceError),
-        }
-    }
+const USAGE: &str = "\
+Usage: lw-lwnx [OPTIONS] <COMMAND>
 
-    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], lwnx::LwnxError> {
-        match self.read(data) {
-            Ok(bytes) => Ok(bytes),
-            Err(_) => Err(lwnx::LwnxError::DeviceError),
-        }
-    }
+Commands:
+  info                   Print the device identification and settings
+  get <name|id>          Read a command by name, or print the raw data of a command id
+  set <name> <value>     Write a command value
+  stream [count]         Print distance measurements, forever or `count` times
+  save                   Save the current settings on the device
+  reset                  Restart the device
+  flash <file>           Update the device firmware from a raw binary image
+  scan-ports             Find devices on the available serial ports
 
-    fn delay_callback(&mut self, duration_ms: u64) {
-        thread::sleep(Duration::from_millis(duration_ms));
-    }
-}
+Options:
+  -p, --port <path>      Serial port to open [default: /dev/ttyACM0, COM5 on Windows]
+  -b, --baud <rate>      Serial baud rate, or `auto` to detect it [default: 921600]
+  -t, --timeout <ms>     Time to wait for each response [default: 500]
+  -r, --retries <count>  Number of attempts for each command [default: 4]
+      --trace            Print the raw bytes sent and received
+  -h, --help             Print this help
+
+Command names:
+  product-name, hardware-version, firmware-version, serial-number, user-data,
+  token, distance-output, stream, baud-rate";
 
 /// Implementation example for a user struct that references a serial port.
 struct MyPlatform<'a> {
@@ -69,88 +64,379 @@ impl lwnx::UserPlatform for MyPlatform<'_> {
         }
         match self.port.write(data) {
             Ok(bytes_written) => Ok(bytes_written as usize),
-            Err(_) => Err(lwnx::LwnxError::DeviceError),
+            Err(e) => Err(e.into()),
         }
     }
 
     fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], lwnx::LwnxError> {
         match self.port.read(data) {
             Ok(bytes) => {
-                if self.trace_packet {
+                if self.trace_packet && !bytes.is_empty() {
                     println!("Read: {:X?}", bytes);
                 }
                 Ok(bytes)
             }
-            Err(_) => Err(lwnx::LwnxError::DeviceError),
+            Err(e) => Err(e.into()),
         }
     }
 
     fn delay_callback(&mut self, duration_ms: u64) {
-        if self.trace_packet {
-            println!("Delay for: {} ms", duration_ms);
-        }
         thread::sleep(Duration::from_millis(duration_ms));
     }
+
+    fn set_read_timeout_callback(&mut self, timeout_ms: u64) -> Result<(), lwnx::LwnxError> {
+        match self.port.set_timeout(timeout_ms) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read_timeout_callback(&mut self) -> Option<u64> {
+        self.port.timeout()
+    }
+
+    fn set_baud_rate_callback(&mut self, baud_rate: u32) -> Result<(), lwnx::LwnxError> {
+        match self.port.set_baud_rate(baud_rate) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
 }
 
-fn main() -> Result<(), String> {
-    println!("Serial port list:");
+/// Subcommand selected on the command line.
+enum Action {
+    Info,
+    Get(String),
+    Set(String, String),
+    Stream(Option<u64>),
+    Save,
+    Reset,
+    Flash(String),
+    ScanPorts,
+    Help,
+}
 
-    let ports = available_ports().unwrap();
+struct Options {
+    port: String,
+    baud: u32,
+    detect_baud: bool,
+    timeout: u64,
+    retries: u32,
+    trace: bool,
+    action: Action,
+}
 
-    for p in ports {
-        println!("{} {:?}", p.port_name, p.port_type);
+fn parse_number<N: std::str::FromStr>(option: &str, value: &str) -> Result<N, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{}' for {}", value, option))
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Options, String> {
+    let mut options = Options {
+        port: DEFAULT_PORT.to_owned(),
+        baud: DEFAULT_BAUD,
+        detect_baud: false,
+        timeout: 500,
+        retries: 4,
+        trace: false,
+        action: Action::Help,
+    };
+    let mut positional = Vec::new();
 
-        match p.port_type {
-            SerialPortType::UsbPort(info) => println!("Serial: {:?}", info.serial_number),
-            _ => (),
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", option))
         };
+
+        match arg.as_str() {
+            "-p" | "--port" => options.port = value(&arg)?,
+            "-b" | "--baud" => match value(&arg)?.as_str() {
+                "auto" => options.detect_baud = true,
+                baud => options.baud = parse_number(&arg, baud)?,
+            },
+            "-t" | "--timeout" => options.timeout = parse_number(&arg, &value(&arg)?)?,
+            "-r" | "--retries" => {
+                options.retries = parse_number(&arg, &value(&arg)?)?;
+                if options.retries == 0 {
+                    return Err(format!("{} must be at least 1", arg));
+                }
+            }
+            "--trace" => options.trace = true,
+            "-h" | "--help" => return Ok(options),
+            _ if arg.starts_with('-') && arg.len() > 1 => {
+                return Err(format!("unknown option '{}'", arg))
+            }
+            _ => positional.push(arg),
+        }
     }
 
-    let mut port = MySerialPort::new();
+    let mut positional = positional.into_iter();
+    let command = match positional.next() {
+        Some(command) => command,
+        None => return Err("no command given".to_owned()),
+    };
+    let mut operand = |name: &str| {
+        positional
+            .next()
+            .ok_or_else(|| format!("{} requires <{}>", command, name))
+    };
 
-    #[cfg(windows)]
-    port.connect("COM5", 921600)?;
+    options.action = match command.as_str() {
+        "info" => Action::Info,
+        "get" => Action::Get(operand("name|id")?),
+        "set" => Action::Set(operand("name")?, operand("value")?),
+        "stream" => match positional.next() {
+            Some(count) => Action::Stream(Some(parse_number("count", &count)?)),
+            None => Action::Stream(None),
+        },
+        "save" => Action::Save,
+        "reset" => Action::Reset,
+        "flash" => Action::Flash(operand("file")?),
+        "scan-ports" => Action::ScanPorts,
+        _ => return Err(format!("unknown command '{}'", command)),
+    };
 
-    #[cfg(unix)]
-    port.connect("/dev/ttyACM0", 921600)?;
-    
-    let my_platform = MyPlatform {
-        port: &mut port,
-        trace_packet: true,
+    if let Some(extra) = positional.next() {
+        return Err(format!("unexpected argument '{}'", extra));
+    }
+
+    Ok(options)
+}
+
+/// Parses a decimal or `0x` prefixed hexadecimal integer.
+fn parse_u32(value: &str) -> Result<u32, String> {
+    let parsed = match value.strip_prefix("0x").or(value.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
     };
-    let mut device_context = lwnx::DeviceContext::new(my_platform);
+    parsed.map_err(|_| format!("invalid number '{}'", value))
+}
 
-    // let mut port = serialport::new("COM5", 921600)
-    //     .timeout(Duration::from_millis(1))
-    //     .open()
-    //     .expect("Failed to open port");
+fn format_version(version: u32) -> String {
+    let [patch, minor, major, _] = version.to_le_bytes();
+    format!("{}.{}.{}", major, minor, patch)
+}
 
-    // // NOTE: Apparently DTR change only required on Windows.
-    // port.write_data_terminal_ready(true).unwrap();
-    // // let mut platform_context = lwnx::PlatformContext::new(port);
-    // let mut device_context = lwnx::DeviceContext::new(port);
+fn format_measurement(measurement: &DistanceMeasurement) -> String {
+    let fields = [
+        ("first_raw", measurement.first_return_raw),
+        ("first_filtered", measurement.first_return_filtered),
+        ("first_strength", measurement.first_return_strength),
+        ("last_raw", measurement.last_return_raw),
+        ("last_filtered", measurement.last_return_filtered),
+        ("last_strength", measurement.last_return_strength),
+        ("noise", measurement.background_noise),
+        ("temperature", measurement.temperature),
+        ("yaw", measurement.yaw_angle),
+    ];
 
-    // Attempt to start LWNX mode.
-    lwnx::engage_lwnx_mode(&mut device_context)?;
+    fields
+        .iter()
+        .filter_map(|(name, value)| value.map(|v| format!("{}={}", name, v)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+type Context<'a> = DeviceContext<MyPlatform<'a>>;
+
+fn info(device_context: &mut Context) -> Result<(), LwnxError> {
+    println!("Model name: {}", device_context.read::<ProductName>()?);
+    println!(
+        "Hardware version: {}",
+        device_context.read::<HardwareVersion>()?
+    );
+    println!(
+        "Firmware version: {}",
+        format_version(device_context.read::<FirmwareVersion>()?)
+    );
+    println!("Serial number: {}", device_context.read::<SerialNumber>()?);
+    println!("User data: {}", device_context.read::<UserData>()?);
+    println!(
+        "Distance output: {:#X}",
+        device_context.read::<DistanceOutput>()?.bits()
+    );
+    Ok(())
+}
 
-    let model_name = lwnx::cmd_read_string(&mut device_context, 0)?;
-    println!("Model name: {}", model_name);
+fn get(device_context: &mut Context, name: &str) -> Result<String, String> {
+    let value = match name {
+        "product-name" => device_context.read::<ProductName>()?,
+        "hardware-version" => device_context.read::<HardwareVersion>()?.to_string(),
+        "firmware-version" => format_version(device_context.read::<FirmwareVersion>()?),
+        "serial-number" => device_context.read::<SerialNumber>()?,
+        "user-data" => device_context.read::<UserData>()?,
+        "token" => format!("{:#06X}", device_context.read::<Token>()?),
+        "distance-output" => format!("{:#X}", device_context.read::<DistanceOutput>()?.bits()),
+        "stream" => device_context.read::<Stream>()?.to_string(),
+        "baud-rate" => baud::read_baud_rate(device_context)?.to_string(),
+        _ => {
+            let command_id: u8 = name
+                .parse()
+                .map_err(|_| format!("unknown command name '{}'", name))?;
+            let mut response = Response::new();
+            lwnx::handle_managed_cmd(device_context, command_id, false, &[], &mut response)?;
+            format!("{:02X?}", response.payload())
+        }
+    };
 
-    let hardware_version = lwnx::cmd_read_u32(&mut device_context, 1)?;
-    println!("Hardware version: {}", hardware_version);
+    Ok(value)
+}
 
-    let firmware_version = lwnx::cmd_read_u32(&mut device_context, 2)?;
-    println!("Firmware version: {}", firmware_version);
+fn set(device_context: &mut Context, name: &str, value: &str) -> Result<(), String> {
+    match name {
+        "user-data" => device_context.write::<UserData>(&value.to_owned())?,
+        "distance-output" => device_context
+            .write::<DistanceOutput>(&DistanceOutputConfig::from_bits(parse_u32(value)?))?,
+        "stream" => device_context.write::<Stream>(&parse_u32(value)?)?,
+        "baud-rate" => baud::set_baud_rate(device_context, parse_u32(value)?)?,
+        _ => return Err(format!("'{}' cannot be set", name)),
+    }
 
-    let serial_number = lwnx::cmd_read_string(&mut device_context, 3)?;
-    println!("Serial number: {}", serial_number);
+    Ok(())
+}
 
-    let user_data = lwnx::cmd_read_string(&mut device_context, 9)?;
-    println!("User data: {}", user_data);
+fn stream_distances(device_context: &mut Context, count: Option<u64>) -> Result<(), LwnxError> {
+    let config = device_context.read::<DistanceOutput>()?;
+    let mut received = 0;
+    let mut result = Ok(());
 
-    let distance_output = lwnx::cmd_read_u32(&mut device_context, 27)?;
-    println!("Distance output: {}", distance_output);
+    for measurement in stream::start_distance_stream(device_context)?.distances(config) {
+        match measurement {
+            Ok(measurement) => {
+                println!("{}", format_measurement(&measurement));
+                received += 1;
+            }
+            Err(e) if e.is_timeout() => continue,
+            Err(e) => {
+                result = Err(e);
+                break;
+            }
+        }
+
+        if count.is_some_and(|count| received >= count) {
+            break;
+        }
+    }
+
+    // Stops the stream after an error too, so the next command is not
+    // answered by streamed packets.
+    let stopped = stream::stop_stream(device_context);
+    result.and(stopped)
+}
+
+fn flash(device_context: &mut Context, path: &str) -> Result<(), String> {
+    let image = FirmwareImage::load(path).map_err(|e| format!("could not load {}: {}", path, e))?;
+    println!("Flashing {} bytes, CRC {:#06X}", image.len(), image.crc());
+
+    FirmwareUpdater::new().update(device_context, &image, |progress| match progress {
+        UpdateProgress::Staging { page, page_count } => {
+            if page % 64 == 0 || page + 1 == page_count {
+                println!("Staging page {}/{}", page + 1, page_count);
+            }
+        }
+        UpdateProgress::Committing => println!("Committing"),
+        UpdateProgress::WaitingForReboot => println!("Waiting for the device to restart"),
+        UpdateProgress::Complete => println!("Complete"),
+    })?;
 
     Ok(())
 }
+
+#[cfg(unix)]
+fn scan_ports(options: &Options) -> Result<(), String> {
+    let discovery = Discovery {
+        bit_rate: options.baud,
+        ..Discovery::new()
+    };
+    let devices = discovery.scan()?;
+
+    if devices.is_empty() {
+        println!("No devices found");
+    }
+
+    for device in devices {
+        let identity = &device.identity;
+        println!(
+            "{}: {} firmware {} serial {}",
+            device.port_name,
+            identity.product_name,
+            format_version(identity.firmware_version),
+            identity.serial_number
+        );
+
+        if let Some(usb) = device.usb {
+            println!(
+                "    USB {:04X}:{:04X} serial {}",
+                usb.vid,
+                usb.pid,
+                usb.serial_number.as_deref().unwrap_or("unknown")
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(windows)]
+fn scan_ports(_options: &Options) -> Result<(), String> {
+    Err("scan-ports is not supported on Windows".to_owned())
+}
+
+fn run(options: Options) -> Result<(), String> {
+    match options.action {
+        Action::Help => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        Action::ScanPorts => return scan_ports(&options),
+        _ => {}
+    }
+
+    let mut port = MySerialPort::new();
+    port.connect(&options.port, options.baud)
+        .map_err(|e| format!("could not open {}: {}", options.port, e))?;
+
+    let my_platform = MyPlatform {
+        port: &mut port,
+        trace_packet: options.trace,
+    };
+    let mut device_context = DeviceContext::new(my_platform);
+    device_context.command_timeout = options.timeout;
+    device_context.command_retries = i32::try_from(options.retries).unwrap_or(i32::MAX);
+
+    // Attempt to start LWNX mode.
+    lwnx::engage_lwnx_mode(&mut device_context)?;
+
+    if options.detect_baud {
+        // Fastest first, since devices default to the highest rate.
+        let mut baud_rates = BAUD_RATES;
+        baud_rates.reverse();
+        let baud_rate = baud::detect_baud_rate(&mut device_context, &baud_rates)?;
+        eprintln!("Device answered at {} baud", baud_rate);
+    }
+
+    match options.action {
+        Action::Info => info(&mut device_context)?,
+        Action::Get(name) => println!("{}", get(&mut device_context, &name)?),
+        Action::Set(name, value) => set(&mut device_context, &name, &value)?,
+        Action::Stream(count) => stream_distances(&mut device_context, count)?,
+        Action::Save => device_context.save_parameters()?,
+        Action::Reset => device_context.reset_device()?,
+        Action::Flash(path) => flash(&mut device_context, &path)?,
+        Action::ScanPorts | Action::Help => unreachable!(),
+    }
+
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args(env::args().skip(1)).and_then(run);
+
+    if let Err(e) = result {
+        eprintln!("error: {}", e);
+        eprintln!("Run with --help for usage.");
+        process::exit(1);
+    }
+}
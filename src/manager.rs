@@ -0,0 +1,438 @@
+//! Running several devices at once, each on its own worker thread.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::commands::{DistanceData, DistanceOutput, SerialNumber};
+use crate::distance::{DistanceMeasurement, DistanceOutputConfig};
+use crate::lwnx::{
+    engage_lwnx_mode, handle_managed_cmd, DeviceContext, LwnxError, Response, UserPlatform,
+};
+use crate::stream;
+
+#[cfg(unix)]
+use crate::linux_serialport::LinuxSerialPort;
+
+/// How a worker gets measurements from its device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AcquisitionMode {
+    /// Reads a distance packet, then waits `interval_ms` before the next read.
+    Poll { interval_ms: u64 },
+    /// Enables the distance stream and forwards every packet pushed by the
+    /// device. The stream is disabled again when the worker stops.
+    Stream,
+}
+
+/// Settings used to open a device.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeviceConfig {
+    /// Name the device is keyed by. `None` keys it by its serial number.
+    pub name: Option<String>,
+    pub port: String,
+    pub baud_rate: u32,
+    pub mode: AcquisitionMode,
+}
+
+/// Number of errors in a row after which a worker stops, unless changed with
+/// `DeviceManager::set_max_consecutive_errors`.
+pub const DEFAULT_MAX_CONSECUTIVE_ERRORS: u32 = 10;
+
+/// A measurement, or the error that replaced it, from one of the devices.
+#[derive(Debug)]
+pub struct Measurement {
+    /// Name the device is keyed by in the manager.
+    pub device: String,
+    /// When the measurement was received.
+    pub timestamp: Instant,
+    pub result: Result<DistanceMeasurement, LwnxError>,
+}
+
+/// Errors from managing devices, as opposed to measuring with them.
+#[derive(Debug)]
+pub enum ManagerError {
+    /// A device with this name has already been added.
+    DuplicateDevice(String),
+    /// The workers of these devices panicked. The devices have been removed.
+    WorkerPanicked(Vec<String>),
+    /// A device failed while it was being opened or added.
+    Device(LwnxError),
+}
+
+impl fmt::Display for ManagerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ManagerError::DuplicateDevice(name) => {
+                write!(f, "device '{}' has already been added", name)
+            }
+            ManagerError::WorkerPanicked(names) => {
+                write!(f, "worker for {} panicked", names.join(", "))
+            }
+            ManagerError::Device(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ManagerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ManagerError::Device(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<LwnxError> for ManagerError {
+    fn from(e: LwnxError) -> ManagerError {
+        ManagerError::Device(e)
+    }
+}
+
+struct ManagedDevice<T: UserPlatform> {
+    mode: AcquisitionMode,
+    /// `None` while a worker owns the context.
+    device_context: Option<DeviceContext<T>>,
+    worker: Option<JoinHandle<DeviceContext<T>>>,
+}
+
+/// Owns a set of devices keyed by name and collects their measurements into
+/// a single channel.
+///
+/// Devices are added while the manager is stopped. `start` moves each device
+/// context to its own worker thread and `stop` hands them back, so devices
+/// can be configured between runs through `device_mut`. The context of a
+/// worker that ends on its own is taken back the next time the device is
+/// accessed or started.
+///
+/// Timeouts while waiting for a packet are not reported. Other errors are sent
+/// in place of a measurement, and a worker stops once it has sent
+/// `max_consecutive_errors` of them in a row. The manager keeps both ends of
+/// the channel, so it stays open while the manager exists.
+pub struct DeviceManager<T: UserPlatform> {
+    devices: BTreeMap<String, ManagedDevice<T>>,
+    stop: Arc<AtomicBool>,
+    max_consecutive_errors: u32,
+    /// Devices whose worker panicked since the last `stop`.
+    panicked: Vec<String>,
+    sender: Sender<Measurement>,
+    receiver: Receiver<Measurement>,
+}
+
+impl<T: UserPlatform> Default for DeviceManager<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: UserPlatform> DeviceManager<T> {
+    pub fn new() -> DeviceManager<T> {
+        let (sender, receiver) = mpsc::channel();
+
+        DeviceManager {
+            devices: BTreeMap::new(),
+            stop: Arc::new(AtomicBool::new(false)),
+            max_consecutive_errors: DEFAULT_MAX_CONSECUTIVE_ERRORS,
+            panicked: Vec::new(),
+            sender,
+            receiver,
+        }
+    }
+
+    /// Adds a device under `name`. Fails if the name is already in use.
+    pub fn add(
+        &mut self,
+        name: &str,
+        device_context: DeviceContext<T>,
+        mode: AcquisitionMode,
+    ) -> Result<(), ManagerError> {
+        if self.devices.contains_key(name) {
+            return Err(ManagerError::DuplicateDevice(name.to_owned()));
+        }
+
+        self.devices.insert(
+            name.to_owned(),
+            ManagedDevice {
+                mode,
+                device_context: Some(device_context),
+                worker: None,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Engages LWNX mode, reads the serial number and adds the device under
+    /// it. Returns the serial number.
+    pub fn add_by_serial(
+        &mut self,
+        mut device_context: DeviceContext<T>,
+        mode: AcquisitionMode,
+    ) -> Result<String, ManagerError> {
+        engage_lwnx_mode(&mut device_context)?;
+        let name = device_context.read::<SerialNumber>()?.to_string();
+        self.add(&name, device_context, mode)?;
+        Ok(name)
+    }
+
+    /// Removes a device and returns its context. Returns `None` if there is no
+    /// device with that name or it is running.
+    pub fn remove(&mut self, name: &str) -> Option<DeviceContext<T>> {
+        self.join_workers(true);
+        if self.devices.get(name)?.worker.is_some() {
+            return None;
+        }
+
+        self.devices.remove(name)?.device_context
+    }
+
+    /// Names of the devices, in order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.devices.keys().map(String::as_str)
+    }
+
+    /// Returns the context of a device. Returns `None` while it is running.
+    pub fn device_mut(&mut self, name: &str) -> Option<&mut DeviceContext<T>> {
+        self.join_workers(true);
+        self.devices.get_mut(name)?.device_context.as_mut()
+    }
+
+    /// Channel receiving the measurements of every device.
+    pub fn measurements(&self) -> &Receiver<Measurement> {
+        &self.receiver
+    }
+
+    /// Sets how many errors in a row stop a worker. Applies to workers started
+    /// afterwards.
+    pub fn set_max_consecutive_errors(&mut self, count: u32) {
+        self.max_consecutive_errors = count.max(1);
+    }
+
+    /// Returns true while any worker has not finished.
+    pub fn is_running(&self) -> bool {
+        self.devices.values().any(|device| {
+            device
+                .worker
+                .as_ref()
+                .is_some_and(|worker| !worker.is_finished())
+        })
+    }
+
+    /// Waits for every worker to finish and takes back the device contexts.
+    /// Devices whose worker panicked are removed.
+    pub fn stop(&mut self) -> Result<(), ManagerError> {
+        self.stop.store(true, Ordering::Relaxed);
+        self.join_workers(false);
+
+        if !self.panicked.is_empty() {
+            return Err(ManagerError::WorkerPanicked(std::mem::take(
+                &mut self.panicked,
+            )));
+        }
+
+        Ok(())
+    }
+
+    /// Joins the workers, or only those that have already finished, and takes
+    /// back their device contexts. Devices whose worker panicked are removed
+    /// and reported by `stop`.
+    fn join_workers(&mut self, finished_only: bool) {
+        for (name, device) in &mut self.devices {
+            let worker = device
+                .worker
+                .take_if(|worker| !finished_only || worker.is_finished());
+
+            if let Some(worker) = worker {
+                match worker.join() {
+                    Ok(device_context) => device.device_context = Some(device_context),
+                    Err(_) => self.panicked.push(name.clone()),
+                }
+            }
+        }
+
+        let panicked = &self.panicked;
+        self.devices.retain(|name, _| !panicked.contains(name));
+    }
+}
+
+impl<T: UserPlatform + Send + 'static> DeviceManager<T> {
+    /// Starts a worker thread for every device that is not running.
+    pub fn start(&mut self) {
+        self.join_workers(true);
+        if !self.is_running() {
+            self.stop = Arc::new(AtomicBool::new(false));
+        }
+
+        for (name, device) in &mut self.devices {
+            if let Some(device_context) = device.device_context.take() {
+                let worker = Worker {
+                    name: name.clone(),
+                    mode: device.mode,
+                    stop: self.stop.clone(),
+                    max_consecutive_errors: self.max_consecutive_errors,
+                    sender: self.sender.clone(),
+                };
+                device.worker = Some(thread::spawn(move || worker.run(device_context)));
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+impl DeviceManager<LinuxSerialPort> {
+    /// Opens the port of each configuration and adds the device. Returns the
+    /// names the devices were added under. If any device fails to open, the
+    /// devices already added by this call are removed again.
+    pub fn open(&mut self, configs: &[DeviceConfig]) -> Result<Vec<String>, ManagerError> {
+        let mut names = Vec::new();
+
+        for config in configs {
+            match self.open_device(config) {
+                Ok(name) => names.push(name),
+                Err(e) => {
+                    for name in &names {
+                        self.remove(name);
+                    }
+                    return Err(e);
+                }
+            }
+        }
+
+        Ok(names)
+    }
+
+    fn open_device(&mut self, config: &DeviceConfig) -> Result<String, ManagerError> {
+        let mut port = LinuxSerialPort::new();
+        port.connect(&config.port, config.baud_rate)
+            .map_err(LwnxError::from)?;
+
+        let mut device_context = DeviceContext::new(port);
+        match &config.name {
+            Some(name) => {
+                engage_lwnx_mode(&mut device_context)?;
+                self.add(name, device_context, config.mode)?;
+                Ok(name.clone())
+            }
+            None => self.add_by_serial(device_context, config.mode),
+        }
+    }
+}
+
+impl<T: UserPlatform> Drop for DeviceManager<T> {
+    fn drop(&mut self) {
+        let _ = self.stop();
+    }
+}
+
+/// State moved to a worker thread.
+struct Worker {
+    name: String,
+    mode: AcquisitionMode,
+    stop: Arc<AtomicBool>,
+    max_consecutive_errors: u32,
+    sender: Sender<Measurement>,
+}
+
+impl Worker {
+    fn run<T: UserPlatform>(self, mut device_context: DeviceContext<T>) -> DeviceContext<T> {
+        let result = match self.mode {
+            AcquisitionMode::Poll { interval_ms } => self.poll(&mut device_context, interval_ms),
+            AcquisitionMode::Stream => self.stream(&mut device_context),
+        };
+
+        if let Err(e) = result {
+            self.send(Err(e));
+        }
+
+        device_context
+    }
+
+    fn poll<T: UserPlatform>(
+        &self,
+        device_context: &mut DeviceContext<T>,
+        interval_ms: u64,
+    ) -> Result<(), LwnxError> {
+        let config = device_context.read::<DistanceOutput>()?;
+        let mut errors = 0;
+
+        while !self.stop.load(Ordering::Relaxed) {
+            self.report(read_distance(device_context, config), &mut errors)?;
+            thread::sleep(Duration::from_millis(interval_ms));
+        }
+
+        Ok(())
+    }
+
+    fn stream<T: UserPlatform>(
+        &self,
+        device_context: &mut DeviceContext<T>,
+    ) -> Result<(), LwnxError> {
+        let config = device_context.read::<DistanceOutput>()?;
+        let mut errors = 0;
+        let mut result = Ok(());
+
+        for measurement in stream::start_distance_stream(device_context)?.distances(config) {
+            if self.stop.load(Ordering::Relaxed) {
+                break;
+            }
+
+            result = self.report(measurement, &mut errors);
+            if result.is_err() {
+                break;
+            }
+        }
+
+        let stopped = stream::stop_stream(device_context);
+        result.and(stopped)
+    }
+
+    /// Sends a measurement or error, skipping timeouts. `errors` counts the
+    /// errors in a row; the one that reaches the limit is returned instead of
+    /// sent, which ends the worker.
+    fn report(
+        &self,
+        result: Result<DistanceMeasurement, LwnxError>,
+        errors: &mut u32,
+    ) -> Result<(), LwnxError> {
+        match result {
+            Err(e) if e.is_timeout() => return Ok(()),
+            Err(e) => {
+                *errors += 1;
+                if *errors >= self.max_consecutive_errors {
+                    return Err(e);
+                }
+                self.send(Err(e));
+            }
+            Ok(measurement) => {
+                *errors = 0;
+                self.send(Ok(measurement));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn send(&self, result: Result<DistanceMeasurement, LwnxError>) {
+        let measurement = Measurement {
+            device: self.name.clone(),
+            timestamp: Instant::now(),
+            result,
+        };
+
+        // The manager holds the receiver and joins its workers before it is
+        // dropped, so sending cannot fail.
+        let _ = self.sender.send(measurement);
+    }
+}
+
+fn read_distance<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+    config: DistanceOutputConfig,
+) -> Result<DistanceMeasurement, LwnxError> {
+    let mut response = Response::new();
+    handle_managed_cmd(device_context, DistanceData::ID, false, &[], &mut response)?;
+    DistanceMeasurement::from_response(config, &response)
+}
@@ -0,0 +1,290 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use crate::baud::{baud_rate_index, BAUD_RATES};
+use crate::commands::{
+    Access, BaudRate, Command, CommitFirmware, DistanceData, DistanceOutput, FirmwareVersion,
+    HardwareVersion, ProductName, Reset, SaveParameters, SerialNumber, StageFirmware, Stream,
+    Token, UserData,
+};
+use crate::distance::{DistanceMeasurement, DistanceOutputConfig};
+use crate::lwnx::{encode_packet_into, LwnxError, ParseResult, Response, UserPlatform};
+use crate::stream::STREAM_DISTANCE;
+
+/// Token handed out by the simulated device.
+pub const SIMULATED_TOKEN: u16 = 0x5A3C;
+
+/// Baud rate of a new simulated device.
+pub const DEFAULT_BAUD_RATE: u32 = 921600;
+
+struct Register {
+    access: Access,
+    data: Vec<u8>,
+}
+
+/// An in-memory LWNX device.
+///
+/// Packets written to the device are parsed and answered from a register
+/// table, so the protocol layer can be exercised without hardware.
+pub struct SimulatedDevice {
+    registers: BTreeMap<u8, Register>,
+    saved_registers: BTreeMap<u8, Vec<u8>>,
+    request: Response,
+    tx_buffer: VecDeque<u8>,
+    /// Sample sent in every streamed distance packet.
+    pub distance_sample: DistanceMeasurement,
+    /// Firmware pages received through the stage command.
+    pub staged_firmware: BTreeMap<u16, Vec<u8>>,
+    pub save_count: u32,
+    pub reset_count: u32,
+    pub commit_count: u32,
+    /// Baud rate the device communicates at.
+    pub baud_rate: u32,
+    /// Ignores changes to the baud rate setting, like a device whose rate is
+    /// fixed by its hardware.
+    pub baud_rate_fixed: bool,
+    /// Baud rate the device switches to once its pending output is sent.
+    pending_baud_rate: Option<u32>,
+    /// Baud rate set by the host. Nothing gets through while it differs from
+    /// `baud_rate`.
+    host_baud_rate: u32,
+}
+
+impl Default for SimulatedDevice {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pads `value` with null bytes to a `size` byte string field. Longer values
+/// are truncated to leave room for the terminator.
+fn string_register(value: &str, size: usize) -> Vec<u8> {
+    let mut data = vec![0u8; size];
+    let len = value.len().min(size.saturating_sub(1));
+    data[..len].copy_from_slice(&value.as_bytes()[..len]);
+    data
+}
+
+impl SimulatedDevice {
+    pub fn new() -> SimulatedDevice {
+        let mut device = SimulatedDevice {
+            registers: BTreeMap::new(),
+            saved_registers: BTreeMap::new(),
+            request: Response::new(),
+            tx_buffer: VecDeque::new(),
+            distance_sample: DistanceMeasurement::default(),
+            staged_firmware: BTreeMap::new(),
+            save_count: 0,
+            reset_count: 0,
+            commit_count: 0,
+            baud_rate: DEFAULT_BAUD_RATE,
+            baud_rate_fixed: false,
+            pending_baud_rate: None,
+            host_baud_rate: DEFAULT_BAUD_RATE,
+        };
+
+        device.add_register::<ProductName>(string_register("SIM01", ProductName::SIZE));
+        device.add_register::<HardwareVersion>(3u32.to_le_bytes().to_vec());
+        device.add_register::<FirmwareVersion>(0x0002_0100u32.to_le_bytes().to_vec());
+        device.add_register::<SerialNumber>(string_register("SIM00001", SerialNumber::SIZE));
+        device.add_register::<UserData>(vec![0u8; UserData::SIZE]);
+        device.add_register::<Token>(SIMULATED_TOKEN.to_le_bytes().to_vec());
+        device.add_register::<DistanceOutput>(
+            DistanceOutputConfig::FIRST_RETURN_RAW
+                .bits()
+                .to_le_bytes()
+                .to_vec(),
+        );
+        device.add_register::<Stream>(0u32.to_le_bytes().to_vec());
+        device.add_register::<BaudRate>(vec![baud_rate_index(DEFAULT_BAUD_RATE).unwrap()]);
+        device.saved_registers = device.snapshot();
+
+        device
+    }
+
+    fn add_register<C: Command>(&mut self, data: Vec<u8>) {
+        self.set_register(C::ID, C::ACCESS, data);
+    }
+
+    /// Adds or replaces a register.
+    pub fn set_register(&mut self, command_id: u8, access: Access, data: Vec<u8>) {
+        self.registers.insert(command_id, Register { access, data });
+    }
+
+    /// Returns the current contents of a register.
+    pub fn register(&self, command_id: u8) -> Option<&[u8]> {
+        self.registers.get(&command_id).map(|r| r.data.as_slice())
+    }
+
+    /// Returns the contents of a register as of the last save.
+    pub fn saved_register(&self, command_id: u8) -> Option<&[u8]> {
+        self.saved_registers.get(&command_id).map(|r| r.as_slice())
+    }
+
+    /// Number of bytes waiting to be read from the device.
+    pub fn pending_bytes(&self) -> usize {
+        self.tx_buffer.len()
+    }
+
+    fn snapshot(&self) -> BTreeMap<u8, Vec<u8>> {
+        self.registers
+            .iter()
+            .map(|(id, r)| (*id, r.data.clone()))
+            .collect()
+    }
+
+    fn register_u32(&self, command_id: u8) -> u32 {
+        match self.register(command_id) {
+            Some(data) if data.len() >= 4 => u32::from_le_bytes(data[0..4].try_into().unwrap()),
+            _ => 0,
+        }
+    }
+
+    fn send_packet(&mut self, command_id: u8, write: bool, data: &[u8]) {
+        let mut packet = Vec::new();
+        // Register data too large for a packet goes unanswered, as on a device
+        // that cannot reply.
+        if encode_packet_into(&mut packet, command_id, write, data).is_ok() {
+            self.tx_buffer.extend(packet);
+        }
+    }
+
+    fn token_matches(data: &[u8]) -> bool {
+        data.len() >= 2 && u16::from_le_bytes([data[0], data[1]]) == SIMULATED_TOKEN
+    }
+
+    fn handle_request(&mut self) {
+        let command_id = self.request.get_command();
+        let write = self.request.is_write();
+        let payload = self.request.payload().to_vec();
+
+        if write {
+            self.handle_write(command_id, &payload);
+        } else if command_id == DistanceData::ID {
+            self.send_distance_packet();
+        } else if let Some(register) = self.registers.get(&command_id) {
+            if register.access != Access::Write {
+                let data = register.data.clone();
+                self.send_packet(command_id, false, &data);
+            }
+        }
+    }
+
+    fn handle_write(&mut self, command_id: u8, payload: &[u8]) {
+        if command_id == SaveParameters::ID {
+            if Self::token_matches(payload) {
+                self.saved_registers = self.snapshot();
+                self.save_count += 1;
+                self.send_packet(command_id, true, payload);
+            }
+        } else if command_id == Reset::ID {
+            if Self::token_matches(payload) {
+                self.reset();
+            }
+        } else if command_id == CommitFirmware::ID {
+            if Self::token_matches(payload) {
+                self.commit_count += 1;
+                self.reset();
+            }
+        } else if command_id == StageFirmware::ID {
+            if payload.len() >= 2 {
+                let page = u16::from_le_bytes([payload[0], payload[1]]);
+                self.staged_firmware.insert(page, payload[2..].to_vec());
+                self.send_packet(command_id, true, payload);
+            }
+        } else if command_id == BaudRate::ID {
+            if let [index] = *payload {
+                if let Some(&baud_rate) = BAUD_RATES.get(index as usize) {
+                    self.registers.get_mut(&command_id).unwrap().data[0] = index;
+                    self.send_packet(command_id, true, payload);
+                    if !self.baud_rate_fixed {
+                        self.pending_baud_rate = Some(baud_rate);
+                    }
+                }
+            }
+        } else if let Some(register) = self.registers.get_mut(&command_id) {
+            if register.access != Access::Read && payload.len() == register.data.len() {
+                register.data.copy_from_slice(payload);
+                self.send_packet(command_id, true, payload);
+            }
+        }
+    }
+
+    /// Restarts the device, restoring the last saved registers.
+    fn reset(&mut self) {
+        for (id, data) in &self.saved_registers {
+            if let Some(register) = self.registers.get_mut(id) {
+                register.data.clone_from(data);
+            }
+        }
+
+        self.request.reset();
+        self.tx_buffer.clear();
+        self.reset_count += 1;
+
+        self.pending_baud_rate = None;
+        if !self.baud_rate_fixed {
+            let index = self.register(BaudRate::ID).map(|data| data[0] as usize);
+            if let Some(&baud_rate) = index.and_then(|index| BAUD_RATES.get(index)) {
+                self.baud_rate = baud_rate;
+            }
+        }
+    }
+
+    fn send_distance_packet(&mut self) {
+        let config = DistanceOutputConfig::from_bits(self.register_u32(DistanceOutput::ID));
+        let payload = self.distance_sample.encode(config);
+        self.send_packet(DistanceData::ID, false, &payload);
+    }
+}
+
+impl UserPlatform for SimulatedDevice {
+    fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        if self.host_baud_rate != self.baud_rate {
+            return Ok(data.len());
+        }
+
+        for b in data {
+            if self.request.parse_data(*b) == ParseResult::Complete {
+                self.handle_request();
+            }
+        }
+
+        Ok(data.len())
+    }
+
+    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError> {
+        if self.host_baud_rate != self.baud_rate {
+            return Ok(&data[..0]);
+        }
+
+        if self.tx_buffer.is_empty() && self.register_u32(Stream::ID) == STREAM_DISTANCE {
+            self.send_distance_packet();
+        }
+
+        let mut size = 0;
+        while size < data.len() {
+            match self.tx_buffer.pop_front() {
+                Some(b) => {
+                    data[size] = b;
+                    size += 1;
+                }
+                None => break,
+            }
+        }
+
+        if self.tx_buffer.is_empty() {
+            if let Some(baud_rate) = self.pending_baud_rate.take() {
+                self.baud_rate = baud_rate;
+            }
+        }
+
+        Ok(&data[..size])
+    }
+
+    fn delay_callback(&mut self, _duration_ms: u64) {}
+
+    fn set_baud_rate_callback(&mut self, baud_rate: u32) -> Result<(), LwnxError> {
+        self.host_baud_rate = baud_rate;
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+use crate::commands::{DistanceData, Stream};
+use crate::distance::{DistanceMeasurement, DistanceOutputConfig};
+use crate::lwnx::{recv_packet, DeviceContext, LwnxError, Response, UserPlatform};
+
+/// Stream value that enables the distance data stream.
+pub const STREAM_DISTANCE: u32 = 5;
+
+/// Stream value that disables streaming.
+pub const STREAM_DISABLED: u32 = 0;
+
+/// Iterator over packets pushed by the device while streaming.
+///
+/// Each call to `next` waits up to the command timeout for the next packet with
+/// the matching command id. Timeouts are yielded as errors so the caller can
+/// decide whether to keep waiting. Any other error, such as a closed device,
+/// is yielded once and ends the stream.
+pub struct PacketStream<'a, T: UserPlatform> {
+    device_context: &'a mut DeviceContext<T>,
+    command_id: u8,
+    ended: bool,
+}
+
+impl<'a, T: UserPlatform> PacketStream<'a, T> {
+    pub fn new(device_context: &'a mut DeviceContext<T>, command_id: u8) -> PacketStream<'a, T> {
+        PacketStream {
+            device_context,
+            command_id,
+            ended: false,
+        }
+    }
+
+    /// Decodes each streamed packet as a distance measurement using `config`.
+    ///
+    /// `config` must match the distance output configuration set on the device.
+    pub fn distances(
+        self,
+        config: DistanceOutputConfig,
+    ) -> impl Iterator<Item = Result<DistanceMeasurement, LwnxError>> + 'a {
+        self.map(move |packet| DistanceMeasurement::from_response(config, &packet?))
+    }
+}
+
+impl<T: UserPlatform> Iterator for PacketStream<'_, T> {
+    type Item = Result<Response, LwnxError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.ended {
+            return None;
+        }
+
+        let mut response = Response::new();
+        let timeout = self.device_context.command_timeout;
+
+        match recv_packet(self.device_context, self.command_id, &mut response, timeout) {
+            Ok(_) => Some(Ok(response)),
+            Err(e) if e.is_timeout() => Some(Err(e)),
+            Err(e) => {
+                self.ended = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Enables the distance stream and returns an iterator over distance packets.
+pub fn start_distance_stream<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+) -> Result<PacketStream<'_, T>, LwnxError> {
+    device_context.write::<Stream>(&STREAM_DISTANCE)?;
+    Ok(PacketStream::new(device_context, DistanceData::ID))
+}
+
+/// Disables any stream output on the device.
+pub fn stop_stream<T: UserPlatform>(
+    device_context: &mut DeviceContext<T>,
+) -> Result<(), LwnxError> {
+    device_context.write::<Stream>(&STREAM_DISABLED)
+}
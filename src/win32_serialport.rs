@@ -1,4 +1,4 @@
-use std::ffi::CString;
+use std::{ffi::CString, fmt, io, thread, time::Duration};
 
 use winapi::{
     ctypes::c_void,
@@ -17,23 +17,91 @@ use winapi::{
             COMMTIMEOUTS, DCB, DTR_CONTROL_ENABLE, FILE_FLAG_OVERLAPPED, NOPARITY, ONESTOPBIT,
             PURGE_RXABORT, PURGE_RXCLEAR, PURGE_TXABORT, PURGE_TXCLEAR, RTS_CONTROL_ENABLE,
         },
-        winnt::{GENERIC_READ, GENERIC_WRITE, HANDLE},
+        winnt::{GENERIC_READ, GENERIC_WRITE, HANDLE, MAXDWORD},
     },
 };
 
+use crate::lwnx::{LwnxError, UserPlatform};
+
+/// Makes reads return as soon as any byte arrives, or after `timeout_ms` if
+/// none does, instead of waiting for the whole buffer to fill.
+fn set_read_timeouts(timeouts: &mut COMMTIMEOUTS, timeout_ms: u64) {
+    timeouts.ReadIntervalTimeout = MAXDWORD;
+    timeouts.ReadTotalTimeoutMultiplier = MAXDWORD;
+    // Windows only waits for the first byte with a constant strictly between
+    // 0 and MAXDWORD.
+    timeouts.ReadTotalTimeoutConstant = timeout_ms.clamp(1, (MAXDWORD - 1) as u64) as u32;
+}
+
+/// Serial port errors. Failed system calls keep the OS error they reported.
 #[derive(Debug)]
 pub enum WinSerialPortError {
     InvalidSerialPort,
-    WriteFailed,
-    WaitingError,
+    WriteFailed(io::Error),
+    WaitingError(io::Error),
     DidNotWriteAllBytes,
-    ReadPendingError,
-    ReadFailed,
+    ReadPendingError(io::Error),
+    SetTimeoutFailed(io::Error),
+    SetBaudRateFailed(io::Error),
+}
+
+impl WinSerialPortError {
+    /// The OS error behind the failure, if there is one.
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            WinSerialPortError::WriteFailed(e)
+            | WinSerialPortError::WaitingError(e)
+            | WinSerialPortError::ReadPendingError(e)
+            | WinSerialPortError::SetTimeoutFailed(e)
+            | WinSerialPortError::SetBaudRateFailed(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WinSerialPortError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WinSerialPortError::InvalidSerialPort => write!(f, "serial port is not open"),
+            WinSerialPortError::WriteFailed(e) => write!(f, "write failed: {}", e),
+            WinSerialPortError::WaitingError(e) => write!(f, "waiting for I/O failed: {}", e),
+            WinSerialPortError::DidNotWriteAllBytes => write!(f, "did not write all bytes"),
+            WinSerialPortError::ReadPendingError(e) => write!(f, "read failed: {}", e),
+            WinSerialPortError::SetTimeoutFailed(e) => {
+                write!(f, "setting the timeout failed: {}", e)
+            }
+            WinSerialPortError::SetBaudRateFailed(e) => {
+                write!(f, "setting the baud rate failed: {}", e)
+            }
+        }
+    }
+}
+
+impl std::error::Error for WinSerialPortError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.io_error().map(|e| e as _)
+    }
 }
 
 impl From<WinSerialPortError> for String {
     fn from(value: WinSerialPortError) -> Self {
-        std::format!("{:?}", value)
+        value.to_string()
+    }
+}
+
+impl From<WinSerialPortError> for LwnxError {
+    fn from(value: WinSerialPortError) -> Self {
+        match value {
+            WinSerialPortError::WriteFailed(e)
+            | WinSerialPortError::WaitingError(e)
+            | WinSerialPortError::ReadPendingError(e)
+            | WinSerialPortError::SetTimeoutFailed(e)
+            | WinSerialPortError::SetBaudRateFailed(e) => LwnxError::Io(e),
+            WinSerialPortError::InvalidSerialPort => LwnxError::DeviceClosed,
+            WinSerialPortError::DidNotWriteAllBytes => {
+                LwnxError::Io(io::ErrorKind::WriteZero.into())
+            }
+        }
     }
 }
 
@@ -47,6 +115,12 @@ impl Drop for WinSerialPort {
     }
 }
 
+impl Default for WinSerialPort {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl WinSerialPort {
     pub fn new() -> WinSerialPort {
         WinSerialPort {
@@ -124,9 +198,7 @@ impl WinSerialPort {
             }
         }
 
-        timeouts.ReadIntervalTimeout = 0;
-        timeouts.ReadTotalTimeoutMultiplier = 0;
-        timeouts.ReadTotalTimeoutConstant = 10;
+        set_read_timeouts(&mut timeouts, 10);
 
         unsafe {
             if SetCommTimeouts(handle, &mut timeouts) == FALSE {
@@ -151,6 +223,80 @@ impl WinSerialPort {
         self.handle = INVALID_HANDLE_VALUE;
     }
 
+    pub fn set_timeout(&mut self, timeout_ms: u64) -> Result<(), WinSerialPortError> {
+        if self.is_invalid() {
+            return Err(WinSerialPortError::InvalidSerialPort);
+        }
+
+        let mut timeouts = COMMTIMEOUTS::default();
+
+        unsafe {
+            if GetCommTimeouts(self.handle, &mut timeouts) == FALSE {
+                return Err(WinSerialPortError::SetTimeoutFailed(
+                    io::Error::last_os_error(),
+                ));
+            }
+        }
+
+        set_read_timeouts(&mut timeouts, timeout_ms);
+
+        unsafe {
+            if SetCommTimeouts(self.handle, &mut timeouts) == FALSE {
+                return Err(WinSerialPortError::SetTimeoutFailed(
+                    io::Error::last_os_error(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Current read timeout in ms, or `None` if the port is not open.
+    pub fn timeout(&self) -> Option<u64> {
+        if self.is_invalid() {
+            return None;
+        }
+
+        let mut timeouts = COMMTIMEOUTS::default();
+
+        unsafe {
+            if GetCommTimeouts(self.handle, &mut timeouts) == FALSE {
+                return None;
+            }
+        }
+
+        Some(timeouts.ReadTotalTimeoutConstant as u64)
+    }
+
+    pub fn set_baud_rate(&mut self, baud_rate: u32) -> Result<(), WinSerialPortError> {
+        if self.is_invalid() {
+            return Err(WinSerialPortError::InvalidSerialPort);
+        }
+
+        let mut com_params = DCB::default();
+        com_params.DCBlength = std::mem::size_of::<DCB>() as u32;
+
+        unsafe {
+            if GetCommState(self.handle, &mut com_params) == FALSE {
+                return Err(WinSerialPortError::SetBaudRateFailed(
+                    io::Error::last_os_error(),
+                ));
+            }
+        }
+
+        com_params.BaudRate = baud_rate;
+
+        unsafe {
+            if SetCommState(self.handle, &mut com_params) == FALSE {
+                return Err(WinSerialPortError::SetBaudRateFailed(
+                    io::Error::last_os_error(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn write(&self, buffer: &[u8]) -> Result<u32, WinSerialPortError> {
         if self.is_invalid() {
             return Err(WinSerialPortError::InvalidSerialPort);
@@ -169,12 +315,12 @@ impl WinSerialPort {
             ) == FALSE
             {
                 if GetLastError() != ERROR_IO_PENDING {
-                    return Err(WinSerialPortError::WriteFailed);
+                    return Err(WinSerialPortError::WriteFailed(io::Error::last_os_error()));
                 } else {
                     if GetOverlappedResult(self.handle, &mut overlapped, &mut bytes_written, TRUE)
                         == FALSE
                     {
-                        return Err(WinSerialPortError::WaitingError);
+                        return Err(WinSerialPortError::WaitingError(io::Error::last_os_error()));
                     }
                 }
             }
@@ -205,12 +351,14 @@ impl WinSerialPort {
             ) == FALSE
             {
                 if GetLastError() != ERROR_IO_PENDING {
-                    return Err(WinSerialPortError::ReadPendingError);
+                    return Err(WinSerialPortError::ReadPendingError(
+                        io::Error::last_os_error(),
+                    ));
                 } else {
                     if GetOverlappedResult(self.handle, &mut overlapped, &mut bytes_read, TRUE)
                         == FALSE
                     {
-                        return Err(WinSerialPortError::WaitingError);
+                        return Err(WinSerialPortError::WaitingError(io::Error::last_os_error()));
                     }
                 }
             }
@@ -219,3 +367,42 @@ impl WinSerialPort {
         }
     }
 }
+
+/// Implementation example for the LightWare serial port implementation.
+impl UserPlatform for WinSerialPort {
+    fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        match self.write(data) {
+            Ok(bytes_written) => Ok(bytes_written as usize),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError> {
+        match self.read(data) {
+            Ok(bytes) => Ok(bytes),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn delay_callback(&mut self, duration_ms: u64) {
+        thread::sleep(Duration::from_millis(duration_ms));
+    }
+
+    fn set_read_timeout_callback(&mut self, timeout_ms: u64) -> Result<(), LwnxError> {
+        match self.set_timeout(timeout_ms) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn read_timeout_callback(&mut self) -> Option<u64> {
+        self.timeout()
+    }
+
+    fn set_baud_rate_callback(&mut self, baud_rate: u32) -> Result<(), LwnxError> {
+        match self.set_baud_rate(baud_rate) {
+            Ok(_) => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
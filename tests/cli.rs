@@ -0,0 +1,59 @@
+#![cfg(feature = "std")]
+
+use std::process::{Command, Output};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_lw-lwnx"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn prints_help() {
+    let output = run(&["--help"]);
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Usage: lw-lwnx"));
+    assert!(stdout.contains("scan-ports"));
+}
+
+#[test]
+fn rejects_bad_arguments() {
+    for (args, message) in [
+        (&[][..], "no command given"),
+        (&["frobnicate"][..], "unknown command 'frobnicate'"),
+        (&["get"][..], "get requires <name|id>"),
+        (
+            &["--retries", "many", "info"][..],
+            "invalid value 'many' for --retries",
+        ),
+        (
+            &["--retries", "-1", "info"][..],
+            "invalid value '-1' for --retries",
+        ),
+        (&["-r", "0", "info"][..], "-r must be at least 1"),
+        (&["--port"][..], "missing value for --port"),
+        (&["info", "extra"][..], "unexpected argument 'extra'"),
+    ] {
+        let output = run(args);
+
+        assert_eq!(output.status.code(), Some(1), "{:?}", args);
+        let stderr = String::from_utf8(output.stderr).unwrap();
+        assert!(stderr.contains(message), "{:?}: {}", args, stderr);
+    }
+}
+
+#[test]
+fn reports_port_that_cannot_be_opened() {
+    let output = run(&["--port", "/nonexistent/port", "info"]);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(
+        stderr.contains("could not open /nonexistent/port"),
+        "{}",
+        stderr
+    );
+}
@@ -0,0 +1,92 @@
+#![cfg(feature = "std")]
+
+use lw_lwnx::codec::{Frame, LwnxCodec};
+use lw_lwnx::lwnx::MAX_PACKET_SIZE;
+
+fn encode(codec: &LwnxCodec, command_id: u8, write: bool, data: &[u8]) -> Vec<u8> {
+    let mut buffer = [0u8; MAX_PACKET_SIZE];
+    codec
+        .encode(&mut buffer, command_id, write, data)
+        .unwrap()
+        .to_vec()
+}
+
+#[test]
+fn decodes_packets_split_across_reads() {
+    let mut codec = LwnxCodec::new();
+    let mut bytes = vec![0x00, 0x55];
+    bytes.extend(encode(&codec, 3, false, b"SIM00001"));
+    bytes.extend(encode(&codec, 9, true, &[1, 2, 3]));
+
+    let mut frames = Vec::new();
+    for chunk in bytes.chunks(5) {
+        let mut chunk = chunk;
+        while !chunk.is_empty() {
+            let (consumed, response) = codec.decode(chunk);
+            if let Some(response) = response {
+                frames.push(Frame::from(response));
+            }
+            chunk = &chunk[consumed..];
+        }
+    }
+
+    assert_eq!(
+        frames,
+        vec![
+            Frame {
+                command_id: 3,
+                write: false,
+                payload: b"SIM00001".to_vec(),
+            },
+            Frame::write(9, &[1, 2, 3]),
+        ]
+    );
+    assert_eq!(codec.link_stats.dropped_bytes, 2);
+}
+
+#[test]
+fn discards_corrupt_packets() {
+    let mut codec = LwnxCodec::new();
+    let mut bytes = encode(&codec, 1, false, &[4, 0, 0, 0]);
+    bytes[5] ^= 0x10;
+    bytes.extend(encode(&codec, 2, false, &[7, 0, 0, 0]));
+
+    let (consumed, response) = codec.decode(&bytes);
+    assert_eq!(consumed, bytes.len());
+    assert_eq!(response.unwrap().get_command(), 2);
+    assert_eq!(codec.link_stats.crc_errors, 1);
+}
+
+#[cfg(feature = "codec")]
+#[test]
+fn implements_tokio_util_codec() {
+    use bytes::BytesMut;
+    use lw_lwnx::lwnx::{LwnxError, MAX_DATA_SIZE};
+    use tokio_util::codec::{Decoder, Encoder};
+
+    let mut codec = LwnxCodec::new();
+    let mut buffer = BytesMut::new();
+    Encoder::encode(&mut codec, Frame::read(0), &mut buffer).unwrap();
+    Encoder::encode(&mut codec, Frame::write(9, b"abc"), &mut buffer).unwrap();
+    assert!(matches!(
+        Encoder::encode(
+            &mut codec,
+            Frame::write(9, &[0; MAX_DATA_SIZE + 1]),
+            &mut buffer
+        ),
+        Err(LwnxError::PayloadTooLarge { .. })
+    ));
+
+    let mut partial = buffer.split_to(4);
+    assert_eq!(Decoder::decode(&mut codec, &mut partial).unwrap(), None);
+    assert!(partial.is_empty());
+    assert_eq!(
+        Decoder::decode(&mut codec, &mut buffer).unwrap(),
+        Some(Frame::read(0))
+    );
+    assert_eq!(
+        Decoder::decode(&mut codec, &mut buffer).unwrap(),
+        Some(Frame::write(9, b"abc"))
+    );
+    assert!(buffer.is_empty());
+}
@@ -0,0 +1,39 @@
+mod common;
+
+use common::Rng;
+use lw_lwnx::crc::{crc16_bitwise, Crc16};
+
+#[test]
+fn matches_known_check_value() {
+    assert_eq!(Crc16::checksum(b"123456789"), 0x31C3);
+    assert_eq!(crc16_bitwise(b"123456789"), 0x31C3);
+    assert_eq!(Crc16::checksum(&[]), 0);
+}
+
+#[test]
+fn table_matches_bitwise_on_random_inputs() {
+    let mut rng = Rng::new(0x9E37_79B9_7F4A_7C15);
+
+    for _ in 0..2000 {
+        let len = (rng.next_u64() % 1100) as usize;
+        let data = rng.bytes(len);
+        assert_eq!(Crc16::checksum(&data), crc16_bitwise(&data), "{:?}", data);
+    }
+}
+
+#[test]
+fn incremental_updates_match_single_pass() {
+    let mut rng = Rng::new(42);
+
+    for _ in 0..200 {
+        let data = rng.bytes(512);
+        let mut crc = Crc16::new();
+        let mut rest = data.as_slice();
+        while !rest.is_empty() {
+            let split = ((rng.next_u64() % 64) as usize).min(rest.len());
+            crc.update(&rest[..split]);
+            rest = &rest[split..];
+        }
+        assert_eq!(crc.value(), crc16_bitwise(&data));
+    }
+}
@@ -0,0 +1,88 @@
+#![cfg(all(feature = "embedded", feature = "simulator"))]
+
+use std::convert::Infallible;
+
+use embedded_hal::delay::DelayNs;
+use embedded_io::{ErrorType, Read, ReadReady, Write};
+use lw_lwnx::commands::{ProductName, UserData};
+use lw_lwnx::embedded::EmbeddedPlatform;
+use lw_lwnx::lwnx::{self, DeviceContext, UserPlatform};
+use lw_lwnx::simulator::SimulatedDevice;
+
+/// Serial peripheral wired to a simulated device.
+struct MockSerial(SimulatedDevice);
+
+impl ErrorType for MockSerial {
+    type Error = Infallible;
+}
+
+impl Read for MockSerial {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Infallible> {
+        Ok(self.0.read_callback(buf).unwrap().len())
+    }
+}
+
+impl ReadReady for MockSerial {
+    fn read_ready(&mut self) -> Result<bool, Infallible> {
+        Ok(self.0.pending_bytes() > 0)
+    }
+}
+
+impl Write for MockSerial {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Infallible> {
+        Ok(self.0.write_callback(buf).unwrap())
+    }
+
+    fn flush(&mut self) -> Result<(), Infallible> {
+        Ok(())
+    }
+}
+
+/// Delay that records the requested delays instead of waiting.
+#[derive(Default)]
+struct MockDelay(Vec<u32>);
+
+impl DelayNs for MockDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+
+    fn delay_ms(&mut self, ms: u32) {
+        self.0.push(ms);
+    }
+}
+
+fn connect() -> DeviceContext<EmbeddedPlatform<MockSerial, MockDelay, impl FnMut() -> u64>> {
+    let mut time = 0;
+    let clock = move || {
+        time += 1;
+        time
+    };
+
+    let platform = EmbeddedPlatform::new(
+        MockSerial(SimulatedDevice::new()),
+        MockDelay::default(),
+        clock,
+    );
+    DeviceContext::new(platform)
+}
+
+#[test]
+fn reads_and_writes_through_embedded_io() {
+    let mut device_context = connect();
+    lwnx::engage_lwnx_mode(&mut device_context).unwrap();
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    device_context
+        .write::<UserData>(&String::from("mcu"))
+        .unwrap();
+    assert_eq!(device_context.read::<UserData>().unwrap(), "mcu");
+}
+
+#[test]
+fn saturates_long_delays() {
+    let mut device_context = connect();
+    let platform = &mut device_context.user_platform;
+
+    platform.delay_callback(25);
+    platform.delay_callback(u64::MAX);
+    assert_eq!(platform.delay.0, [25, u32::MAX]);
+}
@@ -0,0 +1,164 @@
+#![cfg(feature = "simulator")]
+
+use std::time::Duration;
+
+use lw_lwnx::baud;
+use lw_lwnx::clock::{ManualClock, WithClock};
+use lw_lwnx::commands::{Command, ProductName, SerialNumber};
+use lw_lwnx::fault_injection::{Fault, FaultInjector, FaultRates};
+use lw_lwnx::lwnx::{self, DeviceContext, LwnxError, PollStrategy};
+use lw_lwnx::simulator::SimulatedDevice;
+
+/// Size of a product name response packet.
+const STRING_PACKET_SIZE: usize = 22;
+
+type Platform = FaultInjector<WithClock<SimulatedDevice, ManualClock>>;
+
+/// Connects to a simulated device whose time only advances while waiting, so
+/// timeouts expire without real delays.
+fn connect() -> DeviceContext<Platform> {
+    let platform = WithClock::new(SimulatedDevice::new(), ManualClock::new());
+    let mut device_context = DeviceContext::new(FaultInjector::new(platform));
+    device_context.command_timeout = 20;
+    device_context
+}
+
+#[test]
+fn retries_after_dropped_byte() {
+    let mut device_context = connect();
+    device_context.user_platform.schedule_read(5, Fault::Drop);
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    assert_eq!(device_context.user_platform.fault_count, 1);
+    assert_eq!(device_context.user_platform.write_count(), 2 * 6);
+    assert_eq!(device_context.link_stats.crc_errors, 0);
+}
+
+#[test]
+fn rejects_packet_with_bad_crc() {
+    let mut device_context = connect();
+    device_context
+        .user_platform
+        .schedule_read(10, Fault::BitFlip(3));
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    assert_eq!(device_context.user_platform.write_count(), 2 * 6);
+    assert_eq!(device_context.link_stats.crc_errors, 1);
+    assert_eq!(device_context.link_stats.resyncs, 1);
+}
+
+#[test]
+fn retries_after_delay_past_timeout() {
+    let mut device_context = connect();
+    device_context
+        .user_platform
+        .schedule_read(0, Fault::Delay(50));
+
+    assert_eq!(device_context.read::<SerialNumber>().unwrap(), "SIM00001");
+    assert!(device_context.user_platform.write_count() >= 2 * 6);
+}
+
+#[test]
+fn recovers_from_duplicate_and_truncated_packets() {
+    let mut device_context = connect();
+    let platform = &mut device_context.user_platform;
+    platform.schedule_read(7, Fault::Duplicate);
+    platform.schedule_read(STRING_PACKET_SIZE + 3, Fault::Truncate);
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    assert_eq!(device_context.user_platform.write_count(), 3 * 6);
+}
+
+#[test]
+fn corrupted_write_is_retried() {
+    let mut device_context = connect();
+    device_context
+        .user_platform
+        .schedule_write(3, Fault::BitFlip(0));
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    assert_eq!(device_context.user_platform.write_count(), 2 * 6);
+}
+
+#[test]
+fn exhausts_retries_when_every_response_is_lost() {
+    let mut device_context = connect();
+    device_context.command_retries = 3;
+    for attempt in 0..3 {
+        device_context
+            .user_platform
+            .schedule_write(attempt * 6, Fault::Truncate);
+    }
+
+    let result = lwnx::cmd_read_string(&mut device_context, ProductName::ID);
+    match result {
+        Err(LwnxError::CommandRetriesExhausted {
+            command_id,
+            attempts,
+            elapsed,
+        }) => {
+            assert_eq!(command_id, ProductName::ID);
+            assert_eq!(attempts, 3);
+            assert_eq!(elapsed, Duration::from_millis(3 * 20));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn sleeps_through_the_platform_between_polls() {
+    let mut device_context = connect();
+    device_context.poll_strategy = PollStrategy::Sleep(5);
+    device_context.command_retries = 1;
+    // The manual clock only moves through `delay_callback`, so the timeout
+    // can only expire if every empty poll sleeps.
+    device_context.user_platform.inner.inner.baud_rate = 115200;
+
+    match device_context.read::<ProductName>() {
+        Err(LwnxError::CommandRetriesExhausted { elapsed, .. }) => {
+            assert_eq!(elapsed, Duration::from_millis(20));
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn confirms_baud_rate_change_whose_echo_was_lost() {
+    let mut device_context = connect();
+    device_context.command_retries = 2;
+    // Drops the start of the echo, after the 7 byte response to reading the
+    // current rate. The device switches as soon as the echo has been sent.
+    device_context.user_platform.schedule_read(7, Fault::Drop);
+
+    baud::set_baud_rate(&mut device_context, 115200).unwrap();
+    assert_eq!(device_context.user_platform.fault_count, 1);
+    assert_eq!(device_context.user_platform.inner.inner.baud_rate, 115200);
+    assert_eq!(baud::read_baud_rate(&mut device_context).unwrap(), 115200);
+}
+
+#[test]
+fn random_faults_are_reproducible() {
+    let rates = FaultRates {
+        drop: 0.01,
+        duplicate: 0.01,
+        truncate: 0.0,
+        bit_flip: 0.01,
+    };
+
+    let run = |seed| {
+        let mut device_context = connect();
+        device_context.command_retries = 50;
+        device_context.user_platform.set_random(seed, rates);
+
+        for _ in 0..20 {
+            device_context.read::<ProductName>().unwrap();
+        }
+
+        (
+            device_context.user_platform.fault_count,
+            device_context.user_platform.write_count(),
+        )
+    };
+
+    assert_eq!(run(42), run(42));
+}
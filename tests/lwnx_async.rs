@@ -0,0 +1,79 @@
+#![cfg(all(feature = "tokio", feature = "simulator"))]
+
+use lw_lwnx::lwnx::{LwnxError, UserPlatform};
+use lw_lwnx::lwnx_async::{self, AsyncDeviceContext};
+use lw_lwnx::simulator::SimulatedDevice;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+/// Connects a simulated device to one end of an in-memory pipe.
+fn spawn_device() -> DuplexStream {
+    let (host, mut device_end) = tokio::io::duplex(1024);
+
+    tokio::spawn(async move {
+        let mut device = SimulatedDevice::new();
+        let mut buffer = [0u8; 1024];
+
+        loop {
+            let read = match device_end.read(&mut buffer).await {
+                Ok(0) | Err(_) => return,
+                Ok(read) => read,
+            };
+            device.write_callback(&buffer[..read]).unwrap();
+
+            let reply = device.read_callback(&mut buffer).unwrap();
+            if device_end.write_all(reply).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    host
+}
+
+#[tokio::test]
+async fn reads_from_simulated_device() {
+    let mut device_context = AsyncDeviceContext::new(spawn_device());
+
+    assert_eq!(
+        lwnx_async::cmd_read_string(&mut device_context, 0)
+            .await
+            .unwrap(),
+        "SIM01"
+    );
+    assert_eq!(
+        lwnx_async::cmd_read_u32(&mut device_context, 1)
+            .await
+            .unwrap(),
+        3
+    );
+}
+
+#[tokio::test]
+async fn drives_devices_concurrently() {
+    let tasks: Vec<_> = (0..8)
+        .map(|_| {
+            tokio::spawn(async {
+                let mut device_context = AsyncDeviceContext::new(spawn_device());
+                lwnx_async::cmd_read_string(&mut device_context, 3).await
+            })
+        })
+        .collect();
+
+    for task in tasks {
+        assert_eq!(task.await.unwrap().unwrap(), "SIM00001");
+    }
+}
+
+#[tokio::test]
+async fn times_out_without_device() {
+    let (host, _device_end) = tokio::io::duplex(64);
+    let mut device_context = AsyncDeviceContext::new(host);
+    device_context.command_timeout = 10;
+    device_context.command_retries = 2;
+
+    let result = lwnx_async::cmd_read_u32(&mut device_context, 1).await;
+    assert!(matches!(
+        result,
+        Err(LwnxError::CommandRetriesExhausted { attempts: 2, .. })
+    ));
+}
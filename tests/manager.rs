@@ -0,0 +1,191 @@
+#![cfg(feature = "simulator")]
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use lw_lwnx::commands::Stream;
+use lw_lwnx::distance::DistanceMeasurement;
+use lw_lwnx::lwnx::{self, DeviceContext, LwnxError, UserPlatform};
+use lw_lwnx::manager::{AcquisitionMode, DeviceManager, ManagerError, Measurement};
+use lw_lwnx::simulator::SimulatedDevice;
+use lw_lwnx::stream::STREAM_DISABLED;
+
+fn device(first_return: i16) -> DeviceContext<SimulatedDevice> {
+    let mut device = SimulatedDevice::new();
+    device.distance_sample = DistanceMeasurement {
+        first_return_raw: Some(first_return),
+        ..DistanceMeasurement::default()
+    };
+
+    let mut device_context = DeviceContext::new(device);
+    lwnx::engage_lwnx_mode(&mut device_context).unwrap();
+    device_context
+}
+
+/// Platform that answers the first `answered` commands, then goes silent.
+struct GoesSilent {
+    device: SimulatedDevice,
+    answered: usize,
+}
+
+impl UserPlatform for GoesSilent {
+    fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        if self.answered == 0 {
+            return Ok(data.len());
+        }
+        self.answered -= 1;
+        self.device.write_callback(data)
+    }
+
+    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError> {
+        self.device.read_callback(data)
+    }
+
+    fn delay_callback(&mut self, _duration_ms: u64) {}
+}
+
+fn receive<T: UserPlatform>(manager: &DeviceManager<T>) -> Measurement {
+    manager
+        .measurements()
+        .recv_timeout(Duration::from_secs(5))
+        .unwrap()
+}
+
+#[test]
+fn collects_measurements_from_every_device() {
+    let mut manager = DeviceManager::new();
+    manager
+        .add(
+            "front",
+            device(120),
+            AcquisitionMode::Poll { interval_ms: 1 },
+        )
+        .unwrap();
+    manager
+        .add("rear", device(340), AcquisitionMode::Stream)
+        .unwrap();
+
+    manager.start();
+    assert!(manager.is_running());
+    assert!(manager.device_mut("front").is_none());
+
+    let mut distances = BTreeMap::new();
+    while distances.len() < 2 {
+        let measurement = receive(&manager);
+        distances.insert(
+            measurement.device,
+            measurement.result.unwrap().first_return_raw,
+        );
+    }
+
+    manager.stop().unwrap();
+    assert!(!manager.is_running());
+    assert_eq!(distances["front"], Some(120));
+    assert_eq!(distances["rear"], Some(340));
+
+    let rear = manager.device_mut("rear").unwrap();
+    assert_eq!(rear.read::<Stream>().unwrap(), STREAM_DISABLED);
+}
+
+#[test]
+fn keys_devices_by_name_or_serial() {
+    let mut manager = DeviceManager::new();
+    let serial = manager
+        .add_by_serial(device(0), AcquisitionMode::Stream)
+        .unwrap();
+    manager
+        .add("front", device(0), AcquisitionMode::Stream)
+        .unwrap();
+
+    assert_eq!(serial, "SIM00001");
+    assert_eq!(manager.names().collect::<Vec<_>>(), ["SIM00001", "front"]);
+    assert!(matches!(
+        manager.add("front", device(0), AcquisitionMode::Stream),
+        Err(ManagerError::DuplicateDevice(name)) if name == "front"
+    ));
+
+    assert!(manager.remove("front").is_some());
+    assert_eq!(manager.names().collect::<Vec<_>>(), ["SIM00001"]);
+}
+
+#[test]
+fn stops_a_worker_after_repeated_errors() {
+    let mut device_context = DeviceContext::new(GoesSilent {
+        device: SimulatedDevice::new(),
+        // Only the distance output configuration is read.
+        answered: 1,
+    });
+    device_context.command_timeout = 5;
+    device_context.command_retries = 1;
+
+    let mut manager = DeviceManager::new();
+    manager.set_max_consecutive_errors(3);
+    manager
+        .add(
+            "front",
+            device_context,
+            AcquisitionMode::Poll { interval_ms: 1 },
+        )
+        .unwrap();
+    manager.start();
+
+    for _ in 0..3 {
+        let measurement = receive(&manager);
+        assert_eq!(measurement.device, "front");
+        assert!(matches!(
+            measurement.result,
+            Err(LwnxError::CommandRetriesExhausted { .. })
+        ));
+    }
+    assert!(manager
+        .measurements()
+        .recv_timeout(Duration::from_millis(100))
+        .is_err());
+
+    // The finished worker hands its device back without `stop`.
+    assert!(!manager.is_running());
+    let device_context = manager.device_mut("front").unwrap();
+    device_context.user_platform.answered = 2;
+
+    manager.start();
+    assert!(receive(&manager).result.is_ok());
+    manager.stop().unwrap();
+}
+
+#[test]
+fn restarts_after_stopping() {
+    let mut manager = DeviceManager::new();
+    manager
+        .add("front", device(120), AcquisitionMode::Stream)
+        .unwrap();
+
+    for _ in 0..2 {
+        manager.start();
+        assert_eq!(
+            receive(&manager).result.unwrap().first_return_raw,
+            Some(120)
+        );
+        manager.stop().unwrap();
+        while manager.measurements().try_recv().is_ok() {}
+    }
+}
+
+#[test]
+fn keeps_running_devices_on_remove() {
+    let mut manager = DeviceManager::new();
+    manager
+        .add(
+            "front",
+            device(120),
+            AcquisitionMode::Poll { interval_ms: 1 },
+        )
+        .unwrap();
+
+    manager.start();
+    assert!(manager.remove("front").is_none());
+    assert_eq!(manager.names().collect::<Vec<_>>(), ["front"]);
+    assert!(receive(&manager).result.is_ok());
+
+    manager.stop().unwrap();
+    assert!(manager.remove("front").is_some());
+}
@@ -0,0 +1,136 @@
+use lw_lwnx::lwnx::{
+    create_packet_bytes, decode_string, decode_string_lossy, encode_string, packet_size, LwnxError,
+    ParseResult, Response, MAX_DATA_SIZE, MAX_PACKET_SIZE,
+};
+
+fn parse(command_id: u8, data: &[u8]) -> Response {
+    let mut buffer = [0u8; 1024];
+    let packet = create_packet_bytes(&mut buffer, command_id, false, data).unwrap();
+
+    let mut response = Response::new();
+    let mut result = ParseResult::Incomplete;
+    for &byte in packet {
+        result = response.parse_data(byte);
+    }
+    assert_eq!(result, ParseResult::Complete);
+    response
+}
+
+#[test]
+fn reads_typed_values() {
+    let mut data = Vec::new();
+    data.extend_from_slice(&0x12u8.to_le_bytes());
+    data.extend_from_slice(&(-2i16).to_le_bytes());
+    data.extend_from_slice(&0xDEADBEEFu32.to_le_bytes());
+    data.extend_from_slice(&1.5f32.to_le_bytes());
+    data.extend_from_slice(b"LW20\0\0\0\0");
+    let response = parse(7, &data);
+
+    assert_eq!(response.payload(), data.as_slice());
+    assert_eq!(response.read_u8(0).unwrap(), 0x12);
+    assert_eq!(response.read_i16(1).unwrap(), -2);
+    assert_eq!(response.read_u32(3).unwrap(), 0xDEADBEEF);
+    assert_eq!(response.read_f32(7).unwrap(), 1.5);
+    assert_eq!(response.read_string(11, 8).unwrap(), "LW20");
+    assert_eq!(response.read_array::<2>(1).unwrap(), [0xFE, 0xFF]);
+}
+
+#[test]
+fn rejects_reads_past_payload() {
+    let response = parse(7, &[1, 2, 3]);
+
+    assert_eq!(response.read_u16(1).unwrap(), 0x0302);
+    match response.read_u32(0) {
+        Err(LwnxError::UnexpectedResponseLength {
+            command_id,
+            expected,
+            actual,
+        }) => {
+            assert_eq!(command_id, 7);
+            assert_eq!(expected, 4);
+            assert_eq!(actual, 3);
+        }
+        other => panic!("unexpected result: {:?}", other),
+    }
+    assert!(response.read_bytes(usize::MAX, 2).is_err());
+    assert!(response.get_string_data().is_none());
+}
+
+#[test]
+fn string_without_terminator_uses_whole_field() {
+    let response = parse(0, b"ABCDEFGHIJKLMNOP");
+
+    assert_eq!(response.get_string_data().unwrap(), "ABCDEFGHIJKLMNOP");
+    assert!(matches!(
+        parse(0, &[0xFF, 0]).read_string(0, 2),
+        Err(LwnxError::InvalidData)
+    ));
+}
+
+#[test]
+fn encodes_null_padded_strings() {
+    let mut buffer = [0xFFu8; 32];
+
+    assert_eq!(
+        encode_string(&mut buffer, "LW20", 8).unwrap(),
+        b"LW20\0\0\0\0"
+    );
+    assert!(matches!(
+        encode_string(&mut buffer, "12345678", 8),
+        Err(LwnxError::PayloadTooLarge { size: 8, max: 7 })
+    ));
+    assert!(matches!(
+        encode_string(&mut buffer, "", 64),
+        Err(LwnxError::PayloadTooLarge { size: 64, max: 32 })
+    ));
+
+    assert_eq!(decode_string(b"LW20\0junk").unwrap(), "LW20");
+    assert_eq!(decode_string_lossy(&[0xC3, b'a', 0]), "\u{FFFD}a");
+}
+
+#[test]
+fn encoder_rejects_bad_sizes() {
+    let mut buffer = [0u8; MAX_PACKET_SIZE];
+    let data = vec![0u8; MAX_DATA_SIZE + 1];
+
+    assert!(matches!(
+        create_packet_bytes(&mut buffer, 1, true, &data),
+        Err(LwnxError::PayloadTooLarge {
+            max: MAX_DATA_SIZE,
+            ..
+        })
+    ));
+    assert!(matches!(
+        create_packet_bytes(&mut buffer[..8], 1, true, &[0u8; 4]),
+        Err(LwnxError::BufferTooSmall {
+            required: 10,
+            actual: 8
+        })
+    ));
+    assert_eq!(
+        create_packet_bytes(&mut buffer, 1, true, &data[..MAX_DATA_SIZE])
+            .unwrap()
+            .len(),
+        MAX_PACKET_SIZE
+    );
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn encodes_into_vec() {
+    use lw_lwnx::lwnx::encode_packet_into;
+
+    let mut buffer = [0u8; packet_size(4)];
+    let packet = create_packet_bytes(&mut buffer, 9, true, &[1, 2, 3, 4]).unwrap();
+
+    let mut encoded = vec![0xEE];
+    encode_packet_into(&mut encoded, 9, true, &[1, 2, 3, 4]).unwrap();
+    assert_eq!(encoded[0], 0xEE);
+    assert_eq!(&encoded[1..], packet);
+
+    assert!(matches!(
+        encode_packet_into(&mut encoded, 1, true, &[0u8; MAX_DATA_SIZE + 1]),
+        Err(LwnxError::PayloadTooLarge { .. })
+    ));
+    assert_eq!(encoded.len(), 1 + packet.len());
+}
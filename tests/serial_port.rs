@@ -0,0 +1,440 @@
+#![cfg(all(unix, feature = "simulator"))]
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::{self, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use lw_lwnx::baud;
+use lw_lwnx::commands::{DistanceData, ProductName};
+use lw_lwnx::linux_serialport::LinuxSerialPort;
+use lw_lwnx::lwnx::{DeviceContext, LwnxError, PollStrategy, UserPlatform};
+use lw_lwnx::simulator::SimulatedDevice;
+use lw_lwnx::stream::PacketStream;
+use serialport::{ClearBuffer, DataBits, FlowControl, Parity, SerialPort, StopBits};
+
+/// What the host did to a `MockPort`.
+#[derive(Debug, Default)]
+struct PortLog {
+    writes: usize,
+    reads: usize,
+    timeouts: Vec<Duration>,
+}
+
+/// `serialport::SerialPort` backed by a simulated device.
+///
+/// Like a posix port, a read with no data waits for the timeout and fails
+/// with `TimedOut`.
+struct MockPort {
+    device: SimulatedDevice,
+    /// Errors returned by the next reads, before any data.
+    read_errors: VecDeque<ErrorKind>,
+    write_error: Option<ErrorKind>,
+    /// Most bytes accepted by a single write.
+    max_write_size: usize,
+    /// Number of following writes whose responses are lost.
+    lost_responses: usize,
+    /// Baud rates `set_baud_rate` fails for.
+    rejected_baud_rates: Vec<u32>,
+    timeout: Duration,
+    log: Arc<Mutex<PortLog>>,
+}
+
+impl MockPort {
+    fn new() -> MockPort {
+        MockPort {
+            device: SimulatedDevice::new(),
+            read_errors: VecDeque::new(),
+            write_error: None,
+            max_write_size: usize::MAX,
+            lost_responses: 0,
+            rejected_baud_rates: Vec::new(),
+            timeout: Duration::from_millis(10),
+            log: Arc::default(),
+        }
+    }
+}
+
+impl Read for MockPort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.log.lock().unwrap().reads += 1;
+
+        if let Some(kind) = self.read_errors.pop_front() {
+            return Err(kind.into());
+        }
+
+        if self.device.pending_bytes() == 0 {
+            thread::sleep(self.timeout);
+            return Err(ErrorKind::TimedOut.into());
+        }
+
+        Ok(self.device.read_callback(buf).unwrap().len())
+    }
+}
+
+impl Write for MockPort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.log.lock().unwrap().writes += 1;
+
+        if let Some(kind) = self.write_error {
+            return Err(kind.into());
+        }
+
+        let buf = &buf[..buf.len().min(self.max_write_size)];
+        let written = self.device.write_callback(buf).unwrap();
+
+        if self.lost_responses > 0 {
+            self.lost_responses -= 1;
+            let mut discard = [0u8; 1024];
+            while !self.device.read_callback(&mut discard).unwrap().is_empty() {}
+        }
+
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SerialPort for MockPort {
+    fn name(&self) -> Option<String> {
+        Some(String::from("mock"))
+    }
+
+    fn baud_rate(&self) -> serialport::Result<u32> {
+        Ok(self.device.baud_rate)
+    }
+
+    fn data_bits(&self) -> serialport::Result<DataBits> {
+        Ok(DataBits::Eight)
+    }
+
+    fn flow_control(&self) -> serialport::Result<FlowControl> {
+        Ok(FlowControl::None)
+    }
+
+    fn parity(&self) -> serialport::Result<Parity> {
+        Ok(Parity::None)
+    }
+
+    fn stop_bits(&self) -> serialport::Result<StopBits> {
+        Ok(StopBits::One)
+    }
+
+    fn timeout(&self) -> Duration {
+        self.timeout
+    }
+
+    fn set_baud_rate(&mut self, baud_rate: u32) -> serialport::Result<()> {
+        if self.rejected_baud_rates.contains(&baud_rate) {
+            return Err(io::Error::from(ErrorKind::InvalidInput).into());
+        }
+        self.device.set_baud_rate_callback(baud_rate).unwrap();
+        Ok(())
+    }
+
+    fn set_data_bits(&mut self, _data_bits: DataBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_flow_control(&mut self, _flow_control: FlowControl) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_parity(&mut self, _parity: Parity) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_stop_bits(&mut self, _stop_bits: StopBits) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn set_timeout(&mut self, timeout: Duration) -> serialport::Result<()> {
+        self.log.lock().unwrap().timeouts.push(timeout);
+        self.timeout = timeout;
+        Ok(())
+    }
+
+    fn write_request_to_send(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn write_data_terminal_ready(&mut self, _level: bool) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn read_clear_to_send(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_data_set_ready(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn read_ring_indicator(&mut self) -> serialport::Result<bool> {
+        Ok(false)
+    }
+
+    fn read_carrier_detect(&mut self) -> serialport::Result<bool> {
+        Ok(true)
+    }
+
+    fn bytes_to_read(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn bytes_to_write(&self) -> serialport::Result<u32> {
+        Ok(0)
+    }
+
+    fn clear(&self, _buffer_to_clear: ClearBuffer) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn try_clone(&self) -> serialport::Result<Box<dyn SerialPort>> {
+        Err(io::Error::from(ErrorKind::Unsupported).into())
+    }
+
+    fn set_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+
+    fn clear_break(&self) -> serialport::Result<()> {
+        Ok(())
+    }
+}
+
+/// Platform that passes every `io::Error` of the port through, including
+/// timed out reads.
+struct RawPort(MockPort);
+
+impl UserPlatform for RawPort {
+    fn write_callback(&mut self, data: &[u8]) -> Result<usize, LwnxError> {
+        Ok(self.0.write(data)?)
+    }
+
+    fn read_callback<'a>(&mut self, data: &'a mut [u8]) -> Result<&'a [u8], LwnxError> {
+        let bytes = self.0.read(data)?;
+        Ok(&data[..bytes])
+    }
+
+    fn delay_callback(&mut self, duration_ms: u64) {
+        thread::sleep(Duration::from_millis(duration_ms));
+    }
+
+    fn set_baud_rate_callback(&mut self, baud_rate: u32) -> Result<(), LwnxError> {
+        self.0
+            .set_baud_rate(baud_rate)
+            .map_err(|e| LwnxError::Io(e.into()))
+    }
+}
+
+fn connect(port: MockPort) -> DeviceContext<Box<dyn SerialPort>> {
+    let mut device_context = DeviceContext::new(Box::new(port) as Box<dyn SerialPort>);
+    device_context.command_timeout = 20;
+    device_context.command_retries = 2;
+    device_context
+}
+
+/// Returns the kind of the `io::Error` at the bottom of an error chain.
+fn io_error_kind(error: &LwnxError) -> Option<ErrorKind> {
+    let mut error: &dyn Error = error;
+    loop {
+        if let Some(LwnxError::Io(e)) = error.downcast_ref::<LwnxError>() {
+            return Some(e.kind());
+        }
+        error = error.source()?;
+    }
+}
+
+#[test]
+fn write_errors_keep_command_and_io_error() {
+    let mut port = MockPort::new();
+    port.write_error = Some(ErrorKind::BrokenPipe);
+    let mut device_context = connect(port);
+
+    let error = device_context.read::<ProductName>().unwrap_err();
+    assert!(matches!(error, LwnxError::WriteError { command_id: 0, .. }));
+    assert_eq!(io_error_kind(&error), Some(ErrorKind::BrokenPipe));
+}
+
+#[test]
+fn read_errors_keep_command_and_io_error() {
+    let mut port = MockPort::new();
+    port.read_errors.push_back(ErrorKind::PermissionDenied);
+    let mut device_context = connect(port);
+
+    let error = device_context.read::<ProductName>().unwrap_err();
+    assert!(matches!(error, LwnxError::ReadError { command_id: 0, .. }));
+    assert_eq!(io_error_kind(&error), Some(ErrorKind::PermissionDenied));
+}
+
+#[test]
+fn partial_writes_send_the_whole_packet() {
+    let mut port = MockPort::new();
+    port.max_write_size = 4;
+    let log = port.log.clone();
+    let mut device_context = connect(port);
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    // A 6 byte read request takes two writes.
+    assert_eq!(log.lock().unwrap().writes, 2);
+}
+
+#[test]
+fn serial_port_errors_convert_to_io_errors() {
+    use lw_lwnx::linux_serialport::LinuxSerialPortError;
+
+    let error = LwnxError::from(LinuxSerialPortError::ReadFailed(
+        ErrorKind::BrokenPipe.into(),
+    ));
+    assert_eq!(io_error_kind(&error), Some(ErrorKind::BrokenPipe));
+    assert!(matches!(
+        LwnxError::from(LinuxSerialPortError::InvalidSerialPort),
+        LwnxError::DeviceClosed
+    ));
+}
+
+#[test]
+fn timed_out_reads_count_as_timeouts() {
+    use lw_lwnx::lwnx::ErrorSource;
+
+    let read_error = |kind: ErrorKind| LwnxError::ReadError {
+        command_id: 44,
+        source: ErrorSource::new(LwnxError::Io(kind.into())),
+    };
+    assert!(read_error(ErrorKind::TimedOut).is_timeout());
+    assert!(read_error(ErrorKind::WouldBlock).is_timeout());
+    assert!(!read_error(ErrorKind::BrokenPipe).is_timeout());
+    assert!(!LwnxError::DeviceClosed.is_timeout());
+}
+
+#[test]
+fn timed_out_reads_are_retried() {
+    let mut port = MockPort::new();
+    port.lost_responses = 1;
+    let log = port.log.clone();
+    let mut device_context = connect(port);
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    assert_eq!(log.lock().unwrap().writes, 2);
+}
+
+#[test]
+fn timed_out_reads_are_retried_through_linux_serial_port() {
+    let mut port = MockPort::new();
+    port.lost_responses = 1;
+    let log = port.log.clone();
+
+    let mut device_context = DeviceContext::new(LinuxSerialPort::from_port(Box::new(port)));
+    device_context.command_timeout = 20;
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    assert_eq!(log.lock().unwrap().writes, 2);
+}
+
+#[test]
+fn timed_out_reads_reported_as_errors_are_retried() {
+    let mut port = MockPort::new();
+    port.lost_responses = 1;
+    let log = port.log.clone();
+    let mut device_context = DeviceContext::new(RawPort(port));
+    device_context.command_timeout = 20;
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    assert_eq!(log.lock().unwrap().writes, 2);
+}
+
+#[test]
+fn blocking_receive_sets_and_restores_the_read_timeout() {
+    let mut port = MockPort::new();
+    port.lost_responses = usize::MAX;
+    let log = port.log.clone();
+    let mut device_context = connect(port);
+    device_context.poll_strategy = PollStrategy::Blocking;
+    device_context.command_timeout = 30;
+    device_context.command_retries = 1;
+
+    assert!(matches!(
+        device_context.read::<ProductName>(),
+        Err(LwnxError::CommandRetriesExhausted { attempts: 1, .. })
+    ));
+
+    let log = log.lock().unwrap();
+    // Each read waits out the remaining time instead of polling.
+    assert!(log.reads <= 3, "{} reads", log.reads);
+    assert!(log.timeouts[0] <= Duration::from_millis(30));
+    assert_eq!(log.timeouts.last(), Some(&Duration::from_millis(10)));
+}
+
+#[test]
+fn detects_baud_rate_through_timed_out_reads() {
+    let mut port = MockPort::new();
+    port.device.baud_rate = 115200;
+    port.rejected_baud_rates.push(460800);
+    let log = port.log.clone();
+    let mut device_context = DeviceContext::new(RawPort(port));
+    device_context.command_timeout = 20;
+    device_context.command_retries = 2;
+
+    assert!(matches!(
+        device_context.read::<ProductName>(),
+        Err(LwnxError::CommandRetriesExhausted { attempts: 2, .. })
+    ));
+    assert_eq!(log.lock().unwrap().writes, 2);
+    assert_eq!(
+        baud::detect_baud_rate(&mut device_context, &[921600, 460800, 115200]).unwrap(),
+        115200
+    );
+    assert_eq!(device_context.user_platform.0.device.baud_rate, 115200);
+}
+
+#[test]
+fn baud_rate_detection_reports_port_errors() {
+    let mut port = MockPort::new();
+    port.device.baud_rate = 115200;
+    port.read_errors.push_back(ErrorKind::BrokenPipe);
+    let mut device_context = DeviceContext::new(RawPort(port));
+    device_context.command_timeout = 20;
+    device_context.command_retries = 1;
+
+    let error = baud::detect_baud_rate(&mut device_context, &[921600, 115200]).unwrap_err();
+    assert!(matches!(error, LwnxError::ReadError { .. }));
+    assert_eq!(io_error_kind(&error), Some(ErrorKind::BrokenPipe));
+
+    device_context
+        .user_platform
+        .0
+        .rejected_baud_rates
+        .extend([921600, 115200]);
+    let error = baud::detect_baud_rate(&mut device_context, &[921600, 115200]).unwrap_err();
+    assert_eq!(io_error_kind(&error), Some(ErrorKind::InvalidInput));
+}
+
+#[test]
+fn packet_stream_ends_after_a_port_error() {
+    let mut device_context = DeviceContext::new(RawPort(MockPort::new()));
+    device_context.command_timeout = 20;
+
+    let timeouts: Vec<_> = PacketStream::new(&mut device_context, DistanceData::ID)
+        .take(2)
+        .collect();
+    assert!(matches!(
+        timeouts[..],
+        [
+            Err(LwnxError::PacketTimeout { .. }),
+            Err(LwnxError::PacketTimeout { .. })
+        ]
+    ));
+
+    device_context
+        .user_platform
+        .0
+        .read_errors
+        .push_back(ErrorKind::BrokenPipe);
+    let results: Vec<_> = PacketStream::new(&mut device_context, DistanceData::ID).collect();
+    assert!(matches!(results[..], [Err(LwnxError::ReadError { .. })]));
+}
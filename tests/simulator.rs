@@ -0,0 +1,306 @@
+#![cfg(feature = "simulator")]
+
+use lw_lwnx::baud;
+use lw_lwnx::commands::{
+    Access, BaudRate, Command, DistanceOutput, FirmwareVersion, HardwareVersion, ProductName,
+    SerialNumber, UserData,
+};
+use lw_lwnx::discovery::{self, DeviceIdentity};
+use lw_lwnx::distance::{DistanceMeasurement, DistanceOutputConfig};
+use lw_lwnx::firmware::{FirmwareImage, FirmwareUpdater, UpdateProgress, FIRMWARE_PAGE_SIZE};
+use lw_lwnx::lwnx::{self, DeviceContext, LwnxError, Response};
+use lw_lwnx::simulator::SimulatedDevice;
+use lw_lwnx::stream;
+
+fn connect() -> DeviceContext<SimulatedDevice> {
+    let mut device_context = DeviceContext::new(SimulatedDevice::new());
+    lwnx::engage_lwnx_mode(&mut device_context).unwrap();
+    device_context
+}
+
+#[test]
+fn reads_identification() {
+    let mut device_context = connect();
+
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+    assert_eq!(device_context.read::<HardwareVersion>().unwrap(), 3);
+    assert_eq!(
+        device_context.read::<FirmwareVersion>().unwrap(),
+        0x0002_0100
+    );
+    assert_eq!(device_context.read::<SerialNumber>().unwrap(), "SIM00001");
+}
+
+#[test]
+fn identifies_device() {
+    let mut device_context = DeviceContext::new(SimulatedDevice::new());
+
+    assert_eq!(
+        discovery::identify(&mut device_context).unwrap(),
+        DeviceIdentity {
+            product_name: "SIM01".to_owned(),
+            firmware_version: 0x0002_0100,
+            serial_number: "SIM00001".to_owned(),
+        }
+    );
+}
+
+#[test]
+fn detects_baud_rate() {
+    let mut device = SimulatedDevice::new();
+    device.baud_rate = 115200;
+    let mut device_context = DeviceContext::new(device);
+    device_context.command_timeout = 10;
+    device_context.command_retries = 1;
+
+    assert_eq!(
+        baud::detect_baud_rate(&mut device_context, &[921600, 460800, 115200, 9600]).unwrap(),
+        115200
+    );
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+
+    device_context.user_platform.baud_rate = 9600;
+    assert!(matches!(
+        baud::detect_baud_rate(&mut device_context, &[921600, 115200]),
+        Err(LwnxError::BaudRateNotDetected)
+    ));
+}
+
+#[test]
+fn changes_baud_rate() {
+    let mut device_context = connect();
+
+    baud::set_baud_rate(&mut device_context, 115200).unwrap();
+    assert_eq!(device_context.user_platform.baud_rate, 115200);
+    assert_eq!(baud::read_baud_rate(&mut device_context).unwrap(), 115200);
+    assert_eq!(device_context.read::<ProductName>().unwrap(), "SIM01");
+
+    assert!(matches!(
+        baud::set_baud_rate(&mut device_context, 12345),
+        Err(LwnxError::UnsupportedBaudRate(12345))
+    ));
+}
+
+#[test]
+fn rolls_back_baud_rate_the_device_does_not_follow() {
+    let mut device_context = connect();
+    device_context.command_timeout = 10;
+    device_context.command_retries = 1;
+    device_context.user_platform.baud_rate_fixed = true;
+
+    assert!(matches!(
+        baud::set_baud_rate(&mut device_context, 115200),
+        Err(LwnxError::CommandRetriesExhausted { .. })
+    ));
+    assert_eq!(device_context.user_platform.baud_rate, 921600);
+    assert_eq!(
+        device_context.user_platform.register(BaudRate::ID),
+        Some(&[7][..])
+    );
+    assert_eq!(baud::read_baud_rate(&mut device_context).unwrap(), 921600);
+}
+
+#[test]
+fn writes_are_echoed_and_stored() {
+    let mut device_context = connect();
+
+    device_context
+        .write::<UserData>(&String::from("robot-7"))
+        .unwrap();
+    assert_eq!(device_context.read::<UserData>().unwrap(), "robot-7");
+
+    lwnx::cmd_write_u32(&mut device_context, DistanceOutput::ID, 0x1F).unwrap();
+    assert_eq!(
+        lwnx::cmd_read_u32(&mut device_context, DistanceOutput::ID).unwrap(),
+        0x1F
+    );
+}
+
+#[test]
+fn read_only_command_is_not_written() {
+    let mut device_context = connect();
+    device_context.command_timeout = 10;
+    device_context.command_retries = 2;
+
+    let result = lwnx::cmd_write_string(&mut device_context, SerialNumber::ID, "other");
+    assert!(matches!(
+        result,
+        Err(LwnxError::CommandRetriesExhausted { .. })
+    ));
+    assert_eq!(device_context.read::<SerialNumber>().unwrap(), "SIM00001");
+}
+
+#[test]
+fn reads_and_writes_variable_size_strings() {
+    let mut device_context = connect();
+    let mut invalid = vec![0u8; 16];
+    invalid[..4].copy_from_slice(&[b'L', b'W', 0xFF, b'X']);
+    device_context
+        .user_platform
+        .set_register(100, Access::ReadWrite, vec![0u8; 32]);
+    device_context
+        .user_platform
+        .set_register(101, Access::Read, invalid);
+
+    let long_name = "a name longer than sixteen bytes";
+    assert!(matches!(
+        lwnx::cmd_write_string_sized(&mut device_context, 100, long_name, 32),
+        Err(LwnxError::PayloadTooLarge { max: 31, .. })
+    ));
+    lwnx::cmd_write_string_sized(&mut device_context, 100, &long_name[..31], 32).unwrap();
+    assert_eq!(
+        lwnx::cmd_read_string(&mut device_context, 100).unwrap(),
+        long_name[..31]
+    );
+
+    assert!(matches!(
+        lwnx::cmd_read_string(&mut device_context, 101),
+        Err(LwnxError::InvalidData)
+    ));
+    assert_eq!(
+        lwnx::cmd_read_string_lossy(&mut device_context, 101).unwrap(),
+        "LW\u{FFFD}X"
+    );
+}
+
+#[test]
+fn round_trips_32_byte_user_data() {
+    let mut device_context = connect();
+    let value = String::from("rear-left sensor, mast bracket 3");
+    assert_eq!(value.len(), 32);
+
+    assert!(matches!(
+        device_context.write::<UserData>(&value),
+        Err(LwnxError::PayloadTooLarge { size: 32, max: 31 })
+    ));
+
+    let value = String::from(&value[..31]);
+    device_context.write::<UserData>(&value).unwrap();
+    assert_eq!(device_context.read::<UserData>().unwrap(), value);
+    assert_eq!(
+        device_context
+            .user_platform
+            .register(UserData::ID)
+            .unwrap()
+            .len(),
+        32
+    );
+}
+
+#[test]
+fn save_persists_across_reset() {
+    let mut device_context = connect();
+
+    device_context
+        .write::<UserData>(&String::from("kept"))
+        .unwrap();
+    device_context.save_parameters().unwrap();
+    device_context
+        .write::<UserData>(&String::from("lost"))
+        .unwrap();
+    device_context.reset_device().unwrap();
+
+    assert_eq!(device_context.read::<UserData>().unwrap(), "kept");
+    assert_eq!(device_context.user_platform.save_count, 1);
+    assert_eq!(device_context.user_platform.reset_count, 1);
+}
+
+#[test]
+fn streams_distance_packets() {
+    let mut device_context = connect();
+    let config = DistanceOutputConfig::FIRST_RETURN_RAW
+        | DistanceOutputConfig::LAST_RETURN_STRENGTH
+        | DistanceOutputConfig::TEMPERATURE;
+    let sample = DistanceMeasurement {
+        first_return_raw: Some(1234),
+        last_return_strength: Some(87),
+        temperature: Some(2150),
+        ..Default::default()
+    };
+
+    device_context.write::<DistanceOutput>(&config).unwrap();
+    device_context.user_platform.distance_sample = sample;
+
+    let measurements: Vec<_> = stream::start_distance_stream(&mut device_context)
+        .unwrap()
+        .distances(config)
+        .take(3)
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(measurements, vec![sample; 3]);
+
+    stream::stop_stream(&mut device_context).unwrap();
+}
+
+#[test]
+fn updates_firmware() {
+    let mut device_context = connect();
+    let data: Vec<u8> = (0..300).map(|i| i as u8).collect();
+    let image = FirmwareImage::from_bytes(data.clone()).unwrap();
+    let mut progress = Vec::new();
+
+    FirmwareUpdater::new()
+        .update(&mut device_context, &image, |p| progress.push(p))
+        .unwrap();
+
+    let device = &device_context.user_platform;
+    assert_eq!(device.commit_count, 1);
+    assert_eq!(device.staged_firmware.len(), 3);
+    assert_eq!(
+        device.staged_firmware[&2][..44],
+        data[2 * FIRMWARE_PAGE_SIZE..]
+    );
+    assert_eq!(progress.last(), Some(&UpdateProgress::Complete));
+}
+
+#[test]
+fn reports_firmware_load_errors() {
+    assert!(matches!(
+        FirmwareImage::load("does-not-exist.bin"),
+        Err(LwnxError::Io(e)) if e.kind() == std::io::ErrorKind::NotFound
+    ));
+    assert!(matches!(
+        FirmwareImage::load("firmware.LWF"),
+        Err(LwnxError::Io(e)) if e.kind() == std::io::ErrorKind::InvalidInput
+    ));
+    assert!(matches!(
+        FirmwareImage::from_bytes(Vec::new()),
+        Err(LwnxError::InvalidData)
+    ));
+}
+
+#[test]
+fn rejects_oversized_payload() {
+    let mut device_context = connect();
+
+    let result = lwnx::cmd_write_data(&mut device_context, UserData::ID, &[0u8; 2000]);
+    assert!(matches!(
+        result,
+        Err(LwnxError::PayloadTooLarge { size: 2000, .. })
+    ));
+
+    let result = lwnx::cmd_write_string(
+        &mut device_context,
+        UserData::ID,
+        "far too long for the field",
+    );
+    assert!(matches!(
+        result,
+        Err(LwnxError::PayloadTooLarge { max: 15, .. })
+    ));
+}
+
+#[test]
+fn keeps_bytes_of_following_packets() {
+    let mut device_context = DeviceContext::new(SimulatedDevice::new());
+    let mut response = Response::new();
+
+    lwnx::send_cmd(&mut device_context, SerialNumber::ID, false, &[]).unwrap();
+    lwnx::send_cmd(&mut device_context, HardwareVersion::ID, false, &[]).unwrap();
+
+    lwnx::recv_packet(&mut device_context, SerialNumber::ID, &mut response, 10).unwrap();
+    assert_eq!(device_context.user_platform.pending_bytes(), 0);
+
+    lwnx::recv_packet(&mut device_context, HardwareVersion::ID, &mut response, 10).unwrap();
+    assert_eq!(response.payload(), 3u32.to_le_bytes());
+}